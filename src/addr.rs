@@ -0,0 +1,176 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use futures::stream::TryStreamExt;
+use netlink_packet_route::address::{AddressAttribute, AddressFlag, AddressMessage};
+
+use crate::{Result, VethError, VethLink};
+
+/// An IPv4 or IPv6 address with its prefix length, as assigned to a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LinkAddress {
+    addr: IpAddr,
+    prefix_len: u8,
+    nodad: bool,
+    noprefixroute: bool,
+}
+
+impl LinkAddress {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self> {
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_len {
+            return Err(VethError::InvalidArgument(format!(
+                "invalid prefix length {} for {}",
+                prefix_len, addr
+            )));
+        }
+        Ok(Self {
+            addr,
+            prefix_len,
+            nodad: false,
+            noprefixroute: false,
+        })
+    }
+
+    /// Skips duplicate address detection, so IPv6 addresses are usable immediately
+    /// (equivalent to `ip addr add ... nodad`).
+    pub fn nodad(mut self) -> Self {
+        self.nodad = true;
+        self
+    }
+
+    /// Does not install a prefix route for the address's subnet (equivalent to
+    /// `ip addr add ... noprefixroute`).
+    pub fn noprefixroute(mut self) -> Self {
+        self.noprefixroute = true;
+        self
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    fn flags(&self) -> Vec<AddressFlag> {
+        let mut flags = Vec::new();
+        if self.nodad {
+            flags.push(AddressFlag::Nodad);
+        }
+        if self.noprefixroute {
+            flags.push(AddressFlag::Noprefixroute);
+        }
+        flags
+    }
+
+    pub(crate) fn from_message(message: &AddressMessage) -> Option<Self> {
+        let mut addr = None;
+        let mut flags = Vec::new();
+        for attr in &message.attributes {
+            match attr {
+                // IFA_LOCAL is the interface's own address; IFA_ADDRESS may be the peer's on
+                // point-to-point links, so only fall back to it.
+                AddressAttribute::Local(local) => addr = Some(*local),
+                AddressAttribute::Address(address) if addr.is_none() => addr = Some(*address),
+                AddressAttribute::Flags(f) => flags = f.clone(),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            addr: addr?,
+            prefix_len: message.header.prefix_len,
+            nodad: flags.contains(&AddressFlag::Nodad),
+            noprefixroute: flags.contains(&AddressFlag::Noprefixroute),
+        })
+    }
+}
+
+impl FromStr for LinkAddress {
+    type Err = VethError;
+
+    /// Parses CIDR notation such as `10.0.0.1/24` or `fd00::1/64`. A bare address is taken
+    /// as a host address.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || VethError::InvalidArgument(format!("invalid address {:?}", s));
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len.parse().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+        let addr = addr.parse::<IpAddr>().map_err(|_| invalid())?;
+        let prefix_len = prefix_len.unwrap_or(if addr.is_ipv4() { 32 } else { 128 });
+        Self::new(addr, prefix_len)
+    }
+}
+
+impl fmt::Display for LinkAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl VethLink {
+    /// Assigns `address` to this link (equivalent to `ip addr add ADDRESS dev IFNAME`).
+    pub async fn add_address(&self, address: LinkAddress) -> Result<()> {
+        let mut request = self
+            .handle
+            .address()
+            .add(self.index, address.addr, address.prefix_len);
+        let flags = address.flags();
+        if !flags.is_empty() {
+            request
+                .message_mut()
+                .attributes
+                .push(AddressAttribute::Flags(flags));
+        }
+        request.execute().await.map_err(VethError::from)
+    }
+
+    /// Removes `address` from this link (equivalent to `ip addr del ADDRESS dev IFNAME`).
+    pub async fn remove_address(&self, address: LinkAddress) -> Result<()> {
+        let message = self
+            .handle
+            .address()
+            .get()
+            .set_link_index_filter(self.index)
+            .set_address_filter(address.addr)
+            .set_prefix_length_filter(address.prefix_len)
+            .execute()
+            .try_next()
+            .await?
+            .ok_or_else(|| {
+                VethError::InvalidArgument(format!(
+                    "address {} is not assigned to {}",
+                    address, self.ifname
+                ))
+            })?;
+
+        self.handle
+            .address()
+            .del(message)
+            .execute()
+            .await
+            .map_err(VethError::from)
+    }
+
+    /// Lists the addresses currently assigned to this link, including kernel-assigned
+    /// IPv6 link-local addresses.
+    pub async fn addresses(&self) -> Result<Vec<LinkAddress>> {
+        let messages: Vec<AddressMessage> = self
+            .handle
+            .address()
+            .get()
+            .set_link_index_filter(self.index)
+            .execute()
+            .try_collect()
+            .await?;
+
+        Ok(messages
+            .iter()
+            .filter_map(LinkAddress::from_message)
+            .collect())
+    }
+}
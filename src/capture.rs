@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::thread::JoinHandle;
+
+use crate::pcap::{PcapFormat, PcapWriter};
+use crate::{PacketSocket, Result, VethLink};
+
+/// Records the frames on a veth end into a capture file until dropped or stopped.
+///
+/// Frames are recorded in both directions, with the kernel's nanosecond receive timestamps.
+#[derive(Debug)]
+pub struct PcapCapture {
+    /// An eventfd signalled to stop the capture thread.
+    stop: OwnedFd,
+    thread: Option<JoinHandle<Result<usize>>>,
+}
+
+impl PcapCapture {
+    /// Stops the capture and returns the number of frames recorded, reporting any error
+    /// that ended the capture early or occurred while flushing the file.
+    pub fn stop(mut self) -> Result<usize> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<usize> {
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(0),
+        };
+        let res = unsafe { libc::eventfd_write(self.stop.as_raw_fd(), 1) };
+        if res < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("capture thread panicked").into()))
+    }
+}
+
+impl Drop for PcapCapture {
+    fn drop(&mut self) {
+        if let Err(e) = self.join() {
+            eprintln!("pcap capture failed: {}", e);
+        }
+    }
+}
+
+fn capture(
+    socket: PacketSocket,
+    stop: OwnedFd,
+    mut writer: PcapWriter<BufWriter<File>>,
+) -> Result<usize> {
+    let mut buf = vec![0; 65536 + 14];
+    let mut count = 0;
+    let mut stopping = false;
+    loop {
+        match socket.try_recv_timestamped(&mut buf) {
+            Ok((len, timestamp)) => {
+                writer.write_frame(timestamp, &buf[..len])?;
+                count += 1;
+            }
+            // Frames already queued when the capture was stopped are still recorded.
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock && stopping => break,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                socket.wait_readable(&stop)?;
+                let mut value = 0;
+                stopping = unsafe { libc::eventfd_read(stop.as_raw_fd(), &mut value) } == 0;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+impl VethLink {
+    /// Starts recording every frame sent or received on this link into the file at `path`,
+    /// in pcapng format if its extension is `.pcapng` and classic pcap otherwise.
+    ///
+    /// Recording happens on a background thread and continues until the returned guard is
+    /// dropped or [`PcapCapture::stop`] is called.
+    pub fn capture_to_pcap(&self, path: impl AsRef<Path>) -> Result<PcapCapture> {
+        let path = path.as_ref();
+        let socket = self.open_packet_socket(true)?;
+        let file = BufWriter::new(File::create(path)?);
+        let writer = PcapWriter::new(file, PcapFormat::from_path(path), &self.ifname)?;
+
+        let stop = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if stop < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let stop = unsafe { OwnedFd::from_raw_fd(stop) };
+        let thread_stop = stop.try_clone()?;
+        let thread = std::thread::spawn(move || capture(socket, thread_stop, writer));
+
+        Ok(PcapCapture {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::Path;
+
+use futures::stream::TryStreamExt;
+use netlink_packet_route::link::{InfoKind, LinkAttribute, LinkInfo, LinkMessage};
+
+use crate::netns::new_connection_in;
+use crate::{delete_link, Result, VethError};
+
+const OWNER_MARKER: &str = "veth-util-rs pid=";
+
+/// The `ifalias` written on links created without an explicit alias, recording the owning
+/// process so that leftovers can be identified after it dies.
+pub(crate) fn owner_alias() -> String {
+    format!("{}{}", OWNER_MARKER, std::process::id())
+}
+
+/// Selects the veth links removed by [`cleanup_stale_matching`].
+///
+/// A link matches if its name starts with the prefix, or if it was created by this crate in
+/// any process (see [`StaleFilter::owned`]). Only veth links are ever considered.
+#[derive(Debug, Clone, Default)]
+pub struct StaleFilter {
+    prefix: Option<String>,
+    owned: bool,
+    dead_owner_only: bool,
+}
+
+impl StaleFilter {
+    /// Matches veth links whose name starts with `prefix`.
+    pub fn prefix(prefix: &str) -> Self {
+        Self {
+            prefix: Some(prefix.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Matches veth links carrying the ownership marker this crate writes to `ifalias`.
+    pub fn owned() -> Self {
+        Self {
+            owned: true,
+            ..Default::default()
+        }
+    }
+
+    /// Only removes links whose owning process is no longer alive. Links without an
+    /// ownership marker are left alone.
+    pub fn dead_owner_only(mut self) -> Self {
+        self.dead_owner_only = true;
+        self
+    }
+
+    fn matches(&self, ifname: &str, owner: Option<u32>) -> bool {
+        let selected = self
+            .prefix
+            .as_deref()
+            .is_some_and(|p| ifname.starts_with(p))
+            || (self.owned && owner.is_some());
+        if !selected {
+            return false;
+        }
+        if self.dead_owner_only {
+            return owner.is_some_and(|pid| !Path::new(&format!("/proc/{}", pid)).exists());
+        }
+        true
+    }
+}
+
+pub(crate) fn is_veth(link: &LinkMessage) -> bool {
+    link.attributes.iter().any(|attr| match attr {
+        LinkAttribute::LinkInfo(infos) => infos
+            .iter()
+            .any(|info| matches!(info, LinkInfo::Kind(InfoKind::Veth))),
+        _ => false,
+    })
+}
+
+fn link_name_and_owner(link: &LinkMessage) -> (Option<&str>, Option<u32>) {
+    let mut ifname = None;
+    let mut owner = None;
+    for attr in &link.attributes {
+        match attr {
+            LinkAttribute::IfName(name) => ifname = Some(name.as_str()),
+            LinkAttribute::IfAlias(alias) => {
+                owner = alias
+                    .strip_prefix(OWNER_MARKER)
+                    .and_then(|pid| pid.parse().ok())
+            }
+            _ => {}
+        }
+    }
+    (ifname, owner)
+}
+
+/// Deletes veth links left behind by killed test processes whose name starts with `prefix`.
+///
+/// Returns the names of the links that were deleted. See [`cleanup_stale_matching`].
+pub async fn cleanup_stale(prefix: &str) -> Result<Vec<String>> {
+    cleanup_stale_matching(&StaleFilter::prefix(prefix)).await
+}
+
+/// Deletes the veth links in the caller's namespace selected by `filter`.
+///
+/// Deleting one end of a pair also removes its peer; a peer that matched as well is only
+/// reported if it was deleted first. Links in other namespaces are not visited.
+pub async fn cleanup_stale_matching(filter: &StaleFilter) -> Result<Vec<String>> {
+    let (handle, join_handle) = new_connection_in(None)?;
+
+    let res = async {
+        let links: Vec<LinkMessage> = handle.link().get().execute().try_collect().await?;
+        let mut deleted = Vec::new();
+        for link in links.iter().filter(|link| is_veth(link)) {
+            let (ifname, owner) = link_name_and_owner(link);
+            let ifname = match ifname {
+                Some(ifname) if filter.matches(ifname, owner) => ifname,
+                _ => continue,
+            };
+            match delete_link(&handle, ifname, link.header.index).await {
+                Ok(()) => deleted.push(ifname.to_string()),
+                Err(VethError::LinkVanished(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(deleted)
+    }
+    .await;
+
+    join_handle.abort();
+    res
+}
@@ -0,0 +1,107 @@
+use std::io;
+
+use bytes::Bytes;
+use nix::errno::Errno;
+
+/// Errors returned by this crate.
+#[derive(Debug, thiserror::Error)]
+pub enum VethError {
+    /// An interface with this name already exists (`EEXIST`).
+    #[error("interface {0} already exists")]
+    AlreadyExists(String),
+
+    /// The caller lacks `CAP_NET_ADMIN` (or `CAP_SYS_ADMIN` for namespace operations).
+    #[error("permission denied, CAP_NET_ADMIN is required")]
+    PermissionDenied,
+
+    /// The interface name was rejected.
+    #[error("invalid interface name {name:?}: {reason}")]
+    InvalidName { name: String, reason: String },
+
+    /// The interface disappeared, e.g. because it was deleted or its namespace was torn down.
+    #[error("interface {0} no longer exists")]
+    LinkVanished(String),
+
+    /// An argument other than an interface name was rejected before reaching the kernel.
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
+    /// An operation did not complete within its timeout.
+    #[error("timed out waiting for {0}")]
+    Timeout(String),
+
+    /// A frame arrived that a test expected not to see.
+    #[error("unexpected frame on {ifname} ({} bytes)", frame.len())]
+    UnexpectedFrame { ifname: String, frame: Bytes },
+
+    /// A network namespace could not be opened, created or entered.
+    #[error("network namespace {name}: {source}")]
+    Netns {
+        name: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// Any other error reported by the kernel or the netlink transport.
+    #[error("netlink error: {0}")]
+    Netlink(rtnetlink::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, VethError>;
+
+impl VethError {
+    /// Classifies a netlink error raised by an operation on the interface `ifname` itself.
+    ///
+    /// Not for requests on addresses, routes or neighbors: an `EEXIST` there means the entry
+    /// is already present, not that the name is taken, so those are left as
+    /// [`VethError::Netlink`].
+    pub(crate) fn for_link(ifname: &str, err: rtnetlink::Error) -> Self {
+        match netlink_errno(&err) {
+            Some(Errno::EEXIST) => VethError::AlreadyExists(ifname.into()),
+            Some(Errno::ENODEV) => VethError::LinkVanished(ifname.into()),
+            _ => err.into(),
+        }
+    }
+
+    /// Classifies an error raised while handling the namespace `name`.
+    pub(crate) fn netns(name: impl ToString, source: impl Into<io::Error>) -> Self {
+        let source = source.into();
+        match source.kind() {
+            io::ErrorKind::PermissionDenied => VethError::PermissionDenied,
+            _ => VethError::Netns {
+                name: name.to_string(),
+                source,
+            },
+        }
+    }
+
+    /// The errno reported by the kernel, if this error came from a netlink request.
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            VethError::AlreadyExists(_) => Some(Errno::EEXIST as i32),
+            VethError::PermissionDenied => Some(Errno::EPERM as i32),
+            VethError::LinkVanished(_) => Some(Errno::ENODEV as i32),
+            VethError::Netlink(err) => netlink_errno(err).map(|errno| errno as i32),
+            _ => None,
+        }
+    }
+}
+
+impl From<rtnetlink::Error> for VethError {
+    fn from(err: rtnetlink::Error) -> Self {
+        match netlink_errno(&err) {
+            Some(Errno::EPERM) | Some(Errno::EACCES) => VethError::PermissionDenied,
+            _ => VethError::Netlink(err),
+        }
+    }
+}
+
+pub(crate) fn netlink_errno(err: &rtnetlink::Error) -> Option<Errno> {
+    match err {
+        rtnetlink::Error::NetlinkError(msg) => msg.code.map(|code| Errno::from_i32(-code.get())),
+        _ => None,
+    }
+}
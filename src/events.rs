@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::stream::{self, BoxStream, SelectAll, Stream, StreamExt};
+use netlink_packet_core::NetlinkPayload;
+use netlink_packet_route::link::{LinkAttribute, LinkFlag, LinkMessage};
+use netlink_packet_route::RouteNetlinkMessage;
+use rtnetlink::constants::{RTMGRP_IPV4_IFADDR, RTMGRP_IPV6_IFADDR, RTMGRP_LINK};
+use tokio::task::JoinHandle;
+
+use crate::netns::{new_subscription_in, Notifications};
+use crate::{get_link, LinkAddress, Result, VethLink, VethPair};
+
+/// A change to one end of a veth pair, as reported by the kernel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkEvent {
+    ifname: String,
+    kind: LinkEventKind,
+}
+
+impl LinkEvent {
+    /// The name of the end the event happened on.
+    pub fn ifname(&self) -> &str {
+        &self.ifname
+    }
+
+    pub fn kind(&self) -> &LinkEventKind {
+        &self.kind
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkEventKind {
+    /// The link was set administratively up.
+    Up,
+    /// The link was set administratively down.
+    Down,
+    /// The link gained carrier, e.g. because its peer came up.
+    CarrierUp,
+    /// The link lost carrier, e.g. because its peer went down.
+    CarrierDown,
+    /// The link's MTU changed to the given value.
+    MtuChanged(u32),
+    AddressAdded(LinkAddress),
+    AddressRemoved(LinkAddress),
+    /// The link was deleted. No further events follow for it.
+    Deleted,
+    /// The link was moved to another network namespace. No further events follow for it.
+    MovedNetns,
+}
+
+/// A stream of [`LinkEvent`]s, returned by [`VethLink::events`] and [`VethPair::events`].
+///
+/// The stream ends once every watched link has been deleted or moved away.
+pub struct LinkEvents {
+    inner: SelectAll<BoxStream<'static, LinkEvent>>,
+}
+
+impl Stream for LinkEvents {
+    type Item = LinkEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<LinkEvent>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+/// The parts of a link's state that are reported as events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LinkState {
+    up: bool,
+    carrier: bool,
+    mtu: Option<u32>,
+}
+
+impl LinkState {
+    fn from_message(link: &LinkMessage) -> Self {
+        Self {
+            up: link.header.flags.contains(&LinkFlag::Up),
+            carrier: link.header.flags.contains(&LinkFlag::LowerUp),
+            mtu: link.attributes.iter().find_map(|attr| match attr {
+                LinkAttribute::Mtu(mtu) => Some(*mtu),
+                _ => None,
+            }),
+        }
+    }
+}
+
+/// Turns the notifications for one link into events, diffing each link notification
+/// against the last state seen.
+struct Watcher {
+    ifname: String,
+    index: u32,
+    state: LinkState,
+    notifications: Notifications,
+    pending: VecDeque<LinkEventKind>,
+    done: bool,
+    join_handle: JoinHandle<()>,
+}
+
+impl Watcher {
+    async fn next_event(&mut self) -> Option<LinkEvent> {
+        loop {
+            if let Some(kind) = self.pending.pop_front() {
+                return Some(LinkEvent {
+                    ifname: self.ifname.clone(),
+                    kind,
+                });
+            }
+            if self.done {
+                return None;
+            }
+            let (message, _) = self.notifications.next().await?;
+            if let NetlinkPayload::InnerMessage(message) = message.payload {
+                self.handle(message);
+            }
+        }
+    }
+
+    fn handle(&mut self, message: RouteNetlinkMessage) {
+        match message {
+            RouteNetlinkMessage::NewLink(link) if link.header.index == self.index => {
+                let state = LinkState::from_message(&link);
+                if state.up != self.state.up {
+                    self.pending.push_back(if state.up {
+                        LinkEventKind::Up
+                    } else {
+                        LinkEventKind::Down
+                    });
+                }
+                if state.carrier != self.state.carrier {
+                    self.pending.push_back(if state.carrier {
+                        LinkEventKind::CarrierUp
+                    } else {
+                        LinkEventKind::CarrierDown
+                    });
+                }
+                if let Some(mtu) = state.mtu.filter(|mtu| Some(*mtu) != self.state.mtu) {
+                    self.pending.push_back(LinkEventKind::MtuChanged(mtu));
+                }
+                self.state = state;
+            }
+            RouteNetlinkMessage::DelLink(link) if link.header.index == self.index => {
+                // A link leaving the namespace is reported as deleted, along with the id of
+                // the namespace it went to.
+                let moved = link
+                    .attributes
+                    .iter()
+                    .any(|attr| matches!(attr, LinkAttribute::NewNetnsId(_)));
+                self.pending.push_back(if moved {
+                    LinkEventKind::MovedNetns
+                } else {
+                    LinkEventKind::Deleted
+                });
+                self.done = true;
+            }
+            RouteNetlinkMessage::NewAddress(address) if address.header.index == self.index => {
+                if let Some(address) = LinkAddress::from_message(&address) {
+                    self.pending.push_back(LinkEventKind::AddressAdded(address));
+                }
+            }
+            RouteNetlinkMessage::DelAddress(address) if address.header.index == self.index => {
+                if let Some(address) = LinkAddress::from_message(&address) {
+                    self.pending
+                        .push_back(LinkEventKind::AddressRemoved(address));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.join_handle.abort();
+    }
+}
+
+impl VethLink {
+    /// Subscribes to changes of this link, in the namespace the link lives in.
+    ///
+    /// Only changes made after this returns are reported.
+    pub async fn events(&self) -> Result<LinkEvents> {
+        Ok(LinkEvents {
+            inner: stream::select_all(vec![self.watch().await?]),
+        })
+    }
+
+    async fn watch(&self) -> Result<BoxStream<'static, LinkEvent>> {
+        let (handle, notifications, join_handle) = new_subscription_in(
+            self.pinned_netns.as_ref(),
+            RTMGRP_LINK | RTMGRP_IPV4_IFADDR | RTMGRP_IPV6_IFADDR,
+        )?;
+        // Take the initial state after subscribing so that no change falls in between.
+        let state = match get_link(&handle, &self.ifname, self.index).await {
+            Ok(link) => LinkState::from_message(&link),
+            Err(e) => {
+                join_handle.abort();
+                return Err(e);
+            }
+        };
+        let watcher = Watcher {
+            ifname: self.ifname.clone(),
+            index: self.index,
+            state,
+            notifications,
+            pending: VecDeque::new(),
+            done: false,
+            join_handle,
+        };
+
+        Ok(stream::unfold(watcher, |mut watcher| async move {
+            let event = watcher.next_event().await?;
+            Some((event, watcher))
+        })
+        .boxed())
+    }
+}
+
+impl VethPair {
+    /// Subscribes to changes of both ends of the pair. See [`VethLink::events`].
+    pub async fn events(&self) -> Result<LinkEvents> {
+        Ok(LinkEvents {
+            inner: stream::select_all(vec![self.dev1.watch().await?, self.dev2.watch().await?]),
+        })
+    }
+}
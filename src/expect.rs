@@ -0,0 +1,237 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::stream::StreamExt;
+
+use crate::frame::{
+    ETHERTYPE_IPV4, ETHERTYPE_IPV6, ETHERTYPE_QINQ, ETHERTYPE_VLAN, IPPROTO_SCTP, IPPROTO_TCP,
+    IPPROTO_UDP,
+};
+use crate::{FrameStream, Result, VethError};
+
+type Predicate = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// Selects Ethernet frames by their headers, for [`FrameStream::expect_frame`] and
+/// [`FrameStream::expect_no_frame`]. A matcher with no criteria matches every frame.
+#[derive(Clone, Default)]
+pub struct FrameMatcher {
+    ethertype: Option<u16>,
+    vlan: Option<u16>,
+    src_ip: Option<IpAddr>,
+    dst_ip: Option<IpAddr>,
+    ip_protocol: Option<u8>,
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+    predicates: Vec<Predicate>,
+}
+
+/// The headers of a frame that matchers look at.
+#[derive(Debug, Default)]
+struct Headers {
+    ethertype: u16,
+    vlan: Option<u16>,
+    src_ip: Option<IpAddr>,
+    dst_ip: Option<IpAddr>,
+    ip_protocol: Option<u8>,
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+}
+
+fn u16_at(frame: &[u8], offset: usize) -> Option<u16> {
+    let bytes = frame.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+impl Headers {
+    fn parse(frame: &[u8]) -> Option<Self> {
+        let mut headers = Headers::default();
+        let mut offset = 12;
+        headers.ethertype = u16_at(frame, offset)?;
+        offset += 2;
+        // Skip any VLAN tags, remembering the outermost.
+        while headers.ethertype == ETHERTYPE_VLAN || headers.ethertype == ETHERTYPE_QINQ {
+            let tci = u16_at(frame, offset)?;
+            headers.vlan.get_or_insert(tci & 0x0fff);
+            headers.ethertype = u16_at(frame, offset + 2)?;
+            offset += 4;
+        }
+
+        let payload = &frame[offset..];
+        let l4 = match headers.ethertype {
+            ETHERTYPE_IPV4 if payload.len() >= 20 => {
+                let header_len = usize::from(payload[0] & 0x0f) * 4;
+                headers.ip_protocol = Some(payload[9]);
+                headers.src_ip =
+                    Some(Ipv4Addr::from(<[u8; 4]>::try_from(&payload[12..16]).ok()?).into());
+                headers.dst_ip =
+                    Some(Ipv4Addr::from(<[u8; 4]>::try_from(&payload[16..20]).ok()?).into());
+                // Only the first fragment carries the transport header.
+                let fragment_offset = u16_at(payload, 6)? & 0x1fff;
+                if fragment_offset == 0 {
+                    payload.get(header_len..)
+                } else {
+                    None
+                }
+            }
+            ETHERTYPE_IPV6 if payload.len() >= 40 => {
+                headers.ip_protocol = Some(payload[6]);
+                headers.src_ip =
+                    Some(Ipv6Addr::from(<[u8; 16]>::try_from(&payload[8..24]).ok()?).into());
+                headers.dst_ip =
+                    Some(Ipv6Addr::from(<[u8; 16]>::try_from(&payload[24..40]).ok()?).into());
+                payload.get(40..)
+            }
+            _ => None,
+        };
+
+        if let (Some(l4), Some(IPPROTO_TCP | IPPROTO_UDP | IPPROTO_SCTP)) =
+            (l4, headers.ip_protocol)
+        {
+            headers.src_port = u16_at(l4, 0);
+            headers.dst_port = u16_at(l4, 2);
+        }
+        Some(headers)
+    }
+}
+
+impl FrameMatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches the ethertype following any VLAN tags, e.g. `0x0800` for IPv4.
+    pub fn ethertype(mut self, ethertype: u16) -> Self {
+        self.ethertype = Some(ethertype);
+        self
+    }
+
+    /// Matches frames whose outermost VLAN tag carries `vlan_id`.
+    pub fn vlan(mut self, vlan_id: u16) -> Self {
+        self.vlan = Some(vlan_id);
+        self
+    }
+
+    pub fn src_ip(mut self, ip: IpAddr) -> Self {
+        self.src_ip = Some(ip);
+        self
+    }
+
+    pub fn dst_ip(mut self, ip: IpAddr) -> Self {
+        self.dst_ip = Some(ip);
+        self
+    }
+
+    /// Matches the IPv4 protocol or IPv6 next header, e.g. `17` for UDP. IPv6 extension
+    /// headers are not skipped.
+    pub fn ip_protocol(mut self, protocol: u8) -> Self {
+        self.ip_protocol = Some(protocol);
+        self
+    }
+
+    /// Matches the TCP, UDP or SCTP source port.
+    pub fn src_port(mut self, port: u16) -> Self {
+        self.src_port = Some(port);
+        self
+    }
+
+    /// Matches the TCP, UDP or SCTP destination port.
+    pub fn dst_port(mut self, port: u16) -> Self {
+        self.dst_port = Some(port);
+        self
+    }
+
+    /// Additionally requires `predicate` to accept the whole frame.
+    pub fn matching(mut self, predicate: impl Fn(&[u8]) -> bool + Send + Sync + 'static) -> Self {
+        self.predicates.push(Arc::new(predicate));
+        self
+    }
+
+    pub fn matches(&self, frame: &[u8]) -> bool {
+        let headers = match Headers::parse(frame) {
+            Some(headers) => headers,
+            None => return false,
+        };
+        fn check<T: PartialEq>(wanted: Option<T>, actual: Option<T>) -> bool {
+            wanted.is_none() || wanted == actual
+        }
+
+        check(self.ethertype, Some(headers.ethertype))
+            && check(self.vlan, headers.vlan)
+            && check(self.src_ip, headers.src_ip)
+            && check(self.dst_ip, headers.dst_ip)
+            && check(self.ip_protocol, headers.ip_protocol)
+            && check(self.src_port, headers.src_port)
+            && check(self.dst_port, headers.dst_port)
+            && self.predicates.iter().all(|predicate| predicate(frame))
+    }
+}
+
+impl fmt::Debug for FrameMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameMatcher")
+            .field("ethertype", &self.ethertype)
+            .field("vlan", &self.vlan)
+            .field("src_ip", &self.src_ip)
+            .field("dst_ip", &self.dst_ip)
+            .field("ip_protocol", &self.ip_protocol)
+            .field("src_port", &self.src_port)
+            .field("dst_port", &self.dst_port)
+            .field("predicates", &self.predicates.len())
+            .finish()
+    }
+}
+
+impl FrameStream {
+    /// Waits up to `timeout` for a frame accepted by `matcher`, discarding frames that are
+    /// not, and returns it.
+    ///
+    /// Only frames arriving after the stream was opened are seen, so open it before
+    /// triggering the traffic.
+    pub async fn expect_frame(
+        &mut self,
+        matcher: &FrameMatcher,
+        timeout: Duration,
+    ) -> Result<Bytes> {
+        let res = tokio::time::timeout(timeout, self.next_matching(matcher)).await;
+        res.unwrap_or_else(|_| {
+            Err(VethError::Timeout(format!(
+                "a frame matching {:?} on {}",
+                matcher,
+                self.socket().ifname()
+            )))
+        })
+    }
+
+    /// Checks that no frame accepted by `matcher` arrives within `duration`, failing with
+    /// [`VethError::UnexpectedFrame`] as soon as one does.
+    pub async fn expect_no_frame(
+        &mut self,
+        matcher: &FrameMatcher,
+        duration: Duration,
+    ) -> Result<()> {
+        match tokio::time::timeout(duration, self.next_matching(matcher)).await {
+            Err(_) => Ok(()),
+            Ok(Ok(frame)) => Err(VethError::UnexpectedFrame {
+                ifname: self.socket().ifname().into(),
+                frame,
+            }),
+            Ok(Err(e)) => Err(e),
+        }
+    }
+
+    async fn next_matching(&mut self, matcher: &FrameMatcher) -> Result<Bytes> {
+        while let Some(frame) = self.next().await {
+            if matcher.matches(&frame) {
+                return Ok(frame);
+            }
+        }
+        Err(self
+            .take_error()
+            .unwrap_or_else(|| io::Error::other("frame stream ended").into()))
+    }
+}
@@ -0,0 +1,347 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::BitOr;
+
+use crate::{Result, VethError, VethPair};
+
+pub(crate) const ETHERTYPE_IPV4: u16 = 0x0800;
+pub(crate) const ETHERTYPE_ARP: u16 = 0x0806;
+pub(crate) const ETHERTYPE_IPV6: u16 = 0x86dd;
+pub(crate) const ETHERTYPE_VLAN: u16 = 0x8100;
+pub(crate) const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+pub(crate) const IPPROTO_ICMP: u8 = 1;
+pub(crate) const IPPROTO_TCP: u8 = 6;
+pub(crate) const IPPROTO_UDP: u8 = 17;
+pub(crate) const IPPROTO_ICMPV6: u8 = 58;
+pub(crate) const IPPROTO_SCTP: u8 = 132;
+
+const BROADCAST: [u8; 6] = [0xff; 6];
+/// Frames shorter than this (excluding the FCS) are padded, as a NIC would.
+const MIN_FRAME_LEN: usize = 60;
+const TTL: u8 = 64;
+
+/// TCP header flags for [`FrameBuilder::tcp`], combined with `|`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TcpFlags(u8);
+
+impl TcpFlags {
+    pub const FIN: TcpFlags = TcpFlags(0x01);
+    pub const SYN: TcpFlags = TcpFlags(0x02);
+    pub const RST: TcpFlags = TcpFlags(0x04);
+    pub const PSH: TcpFlags = TcpFlags(0x08);
+    pub const ACK: TcpFlags = TcpFlags(0x10);
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+}
+
+impl BitOr for TcpFlags {
+    type Output = TcpFlags;
+
+    fn bitor(self, rhs: TcpFlags) -> TcpFlags {
+        TcpFlags(self.0 | rhs.0)
+    }
+}
+
+/// Builds complete Ethernet frames, with lengths and checksums filled in, from one MAC
+/// address to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameBuilder {
+    src_mac: [u8; 6],
+    dst_mac: [u8; 6],
+    vlan: Option<u16>,
+}
+
+/// The Internet checksum over `data`, continuing from the partial sum `sum`.
+fn checksum(mut sum: u32, data: &[u8]) -> u16 {
+    for chunk in data.chunks(2) {
+        let word = match *chunk {
+            [hi, lo] => u16::from_be_bytes([hi, lo]),
+            [hi] => u16::from_be_bytes([hi, 0]),
+            _ => unreachable!(),
+        };
+        sum += u32::from(word);
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// The partial checksum of the IPv4 or IPv6 pseudo-header covering an upper-layer packet.
+fn pseudo_header_sum(src: IpAddr, dst: IpAddr, protocol: u8, len: usize) -> u32 {
+    let mut header = Vec::with_capacity(40);
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            header.extend_from_slice(&src.octets());
+            header.extend_from_slice(&dst.octets());
+            header.extend_from_slice(&[0, protocol]);
+            header.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        (src, dst) => {
+            header.extend_from_slice(&ipv6_octets(src));
+            header.extend_from_slice(&ipv6_octets(dst));
+            header.extend_from_slice(&(len as u32).to_be_bytes());
+            header.extend_from_slice(&[0, 0, 0, protocol]);
+        }
+    }
+    // Fold without complementing, so the sum can be continued.
+    u32::from(!checksum(0, &header))
+}
+
+fn ipv6_octets(addr: IpAddr) -> [u8; 16] {
+    match addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped().octets(),
+        IpAddr::V6(addr) => addr.octets(),
+    }
+}
+
+fn mixed_families(src: IpAddr, dst: IpAddr) -> VethError {
+    VethError::InvalidArgument(format!(
+        "source {} and destination {} are of different address families",
+        src, dst
+    ))
+}
+
+fn check_families(src: IpAddr, dst: IpAddr) -> Result<()> {
+    if src.is_ipv4() != dst.is_ipv4() {
+        return Err(mixed_families(src, dst));
+    }
+    Ok(())
+}
+
+/// Fills in the checksum at `offset` of an upper-layer packet carried in IP.
+fn with_l4_checksum(
+    mut packet: Vec<u8>,
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    offset: usize,
+) -> Vec<u8> {
+    let sum = pseudo_header_sum(src, dst, protocol, packet.len());
+    let mut checksum = checksum(sum, &packet);
+    // A zero UDP checksum means "none", so a computed zero is sent as all ones.
+    if protocol == IPPROTO_UDP && checksum == 0 {
+        checksum = 0xffff;
+    }
+    packet[offset..offset + 2].copy_from_slice(&checksum.to_be_bytes());
+    packet
+}
+
+impl FrameBuilder {
+    pub fn new(src_mac: [u8; 6], dst_mac: [u8; 6]) -> Self {
+        Self {
+            src_mac,
+            dst_mac,
+            vlan: None,
+        }
+    }
+
+    /// Inserts an 802.1Q tag with `vlan_id` into every frame.
+    pub fn vlan(mut self, vlan_id: u16) -> Self {
+        self.vlan = Some(vlan_id & 0x0fff);
+        self
+    }
+
+    /// A builder for frames travelling in the opposite direction.
+    pub fn reversed(self) -> Self {
+        Self {
+            src_mac: self.dst_mac,
+            dst_mac: self.src_mac,
+            ..self
+        }
+    }
+
+    pub fn src_mac(&self) -> &[u8; 6] {
+        &self.src_mac
+    }
+
+    pub fn dst_mac(&self) -> &[u8; 6] {
+        &self.dst_mac
+    }
+
+    /// An Ethernet frame carrying `payload`, padded to the 60-byte minimum.
+    pub fn ethernet(&self, ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        self.ethernet_to(self.dst_mac, ethertype, payload)
+    }
+
+    fn ethernet_to(&self, dst_mac: [u8; 6], ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(18 + payload.len());
+        frame.extend_from_slice(&dst_mac);
+        frame.extend_from_slice(&self.src_mac);
+        if let Some(vlan) = self.vlan {
+            frame.extend_from_slice(&ETHERTYPE_VLAN.to_be_bytes());
+            frame.extend_from_slice(&vlan.to_be_bytes());
+        }
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(payload);
+        if frame.len() < MIN_FRAME_LEN {
+            frame.resize(MIN_FRAME_LEN, 0);
+        }
+        frame
+    }
+
+    fn arp(
+        &self,
+        op: u16,
+        eth_dst: [u8; 6],
+        target_mac: [u8; 6],
+        sender: Ipv4Addr,
+        target: Ipv4Addr,
+    ) -> Vec<u8> {
+        let mut arp = Vec::with_capacity(28);
+        // Ethernet hardware, IPv4 protocol, address lengths 6 and 4.
+        arp.extend_from_slice(&[0x00, 0x01, 0x08, 0x00, 6, 4]);
+        arp.extend_from_slice(&op.to_be_bytes());
+        arp.extend_from_slice(&self.src_mac);
+        arp.extend_from_slice(&sender.octets());
+        arp.extend_from_slice(&target_mac);
+        arp.extend_from_slice(&target.octets());
+        self.ethernet_to(eth_dst, ETHERTYPE_ARP, &arp)
+    }
+
+    /// A broadcast ARP request from `sender_ip` asking for the MAC of `target_ip`.
+    pub fn arp_request(&self, sender_ip: Ipv4Addr, target_ip: Ipv4Addr) -> Vec<u8> {
+        self.arp(1, BROADCAST, [0; 6], sender_ip, target_ip)
+    }
+
+    /// An ARP reply telling `target_ip` that `sender_ip` is at the source MAC.
+    pub fn arp_reply(&self, sender_ip: Ipv4Addr, target_ip: Ipv4Addr) -> Vec<u8> {
+        self.arp(2, self.dst_mac, self.dst_mac, sender_ip, target_ip)
+    }
+
+    /// An IPv4 packet carrying `payload` as `protocol`, with the header checksum filled in.
+    pub fn ipv4(&self, src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let mut header = Vec::with_capacity(20);
+        header.push(0x45);
+        header.push(0);
+        header.extend_from_slice(&((20 + payload.len()) as u16).to_be_bytes());
+        // Identification 0 with don't-fragment set.
+        header.extend_from_slice(&[0, 0, 0x40, 0]);
+        header.extend_from_slice(&[TTL, protocol, 0, 0]);
+        header.extend_from_slice(&src.octets());
+        header.extend_from_slice(&dst.octets());
+        let checksum = checksum(0, &header);
+        header[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+        header.extend_from_slice(payload);
+        self.ethernet(ETHERTYPE_IPV4, &header)
+    }
+
+    /// An IPv6 packet carrying `payload` with the given next header.
+    pub fn ipv6(&self, src: Ipv6Addr, dst: Ipv6Addr, next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(40 + payload.len());
+        packet.extend_from_slice(&[0x60, 0, 0, 0]);
+        packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[next_header, TTL]);
+        packet.extend_from_slice(&src.octets());
+        packet.extend_from_slice(&dst.octets());
+        packet.extend_from_slice(payload);
+        self.ethernet(ETHERTYPE_IPV6, &packet)
+    }
+
+    /// An IPv4 or IPv6 packet, depending on the address family.
+    pub fn ip(&self, src: IpAddr, dst: IpAddr, protocol: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        match (src, dst) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => Ok(self.ipv4(src, dst, protocol, payload)),
+            (IpAddr::V6(src), IpAddr::V6(dst)) => Ok(self.ipv6(src, dst, protocol, payload)),
+            _ => Err(mixed_families(src, dst)),
+        }
+    }
+
+    /// A UDP datagram from `src` to `dst`, over IPv4 or IPv6.
+    pub fn udp(&self, src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Result<Vec<u8>> {
+        check_families(src.ip(), dst.ip())?;
+        let mut udp = Vec::with_capacity(8 + payload.len());
+        udp.extend_from_slice(&src.port().to_be_bytes());
+        udp.extend_from_slice(&dst.port().to_be_bytes());
+        udp.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(payload);
+        let udp = with_l4_checksum(udp, src.ip(), dst.ip(), IPPROTO_UDP, 6);
+        self.ip(src.ip(), dst.ip(), IPPROTO_UDP, &udp)
+    }
+
+    /// A TCP segment from `src` to `dst` without options, over IPv4 or IPv6.
+    pub fn tcp(
+        &self,
+        src: SocketAddr,
+        dst: SocketAddr,
+        seq: u32,
+        ack: u32,
+        flags: TcpFlags,
+        payload: &[u8],
+    ) -> Result<Vec<u8>> {
+        check_families(src.ip(), dst.ip())?;
+        let mut tcp = Vec::with_capacity(20 + payload.len());
+        tcp.extend_from_slice(&src.port().to_be_bytes());
+        tcp.extend_from_slice(&dst.port().to_be_bytes());
+        tcp.extend_from_slice(&seq.to_be_bytes());
+        tcp.extend_from_slice(&ack.to_be_bytes());
+        // Data offset of five words, then the flags.
+        tcp.extend_from_slice(&[5 << 4, flags.bits()]);
+        tcp.extend_from_slice(&u16::MAX.to_be_bytes());
+        // Checksum and urgent pointer.
+        tcp.extend_from_slice(&[0, 0, 0, 0]);
+        tcp.extend_from_slice(payload);
+        let tcp = with_l4_checksum(tcp, src.ip(), dst.ip(), IPPROTO_TCP, 16);
+        self.ip(src.ip(), dst.ip(), IPPROTO_TCP, &tcp)
+    }
+
+    /// An ICMP (for IPv4) or ICMPv6 message with the given type and code, whose body
+    /// follows the checksum.
+    pub fn icmp(
+        &self,
+        src: IpAddr,
+        dst: IpAddr,
+        kind: u8,
+        code: u8,
+        body: &[u8],
+    ) -> Result<Vec<u8>> {
+        check_families(src, dst)?;
+        let mut icmp = Vec::with_capacity(4 + body.len());
+        icmp.extend_from_slice(&[kind, code, 0, 0]);
+        icmp.extend_from_slice(body);
+        let icmp = match (src, dst) {
+            (IpAddr::V4(_), _) => {
+                let checksum = checksum(0, &icmp);
+                icmp[2..4].copy_from_slice(&checksum.to_be_bytes());
+                icmp
+            }
+            // ICMPv6, unlike ICMP, covers the pseudo-header.
+            _ => with_l4_checksum(icmp, src, dst, IPPROTO_ICMPV6, 2),
+        };
+        let protocol = if src.is_ipv4() {
+            IPPROTO_ICMP
+        } else {
+            IPPROTO_ICMPV6
+        };
+        self.ip(src, dst, protocol, &icmp)
+    }
+
+    /// An ICMP or ICMPv6 echo request ("ping").
+    pub fn icmp_echo_request(
+        &self,
+        src: IpAddr,
+        dst: IpAddr,
+        id: u16,
+        seq: u16,
+        payload: &[u8],
+    ) -> Result<Vec<u8>> {
+        let kind = if src.is_ipv4() { 8 } else { 128 };
+        let mut body = Vec::with_capacity(4 + payload.len());
+        body.extend_from_slice(&id.to_be_bytes());
+        body.extend_from_slice(&seq.to_be_bytes());
+        body.extend_from_slice(payload);
+        self.icmp(src, dst, kind, 0, &body)
+    }
+}
+
+impl VethPair {
+    /// A [`FrameBuilder`] for frames sent out of dev1 towards dev2. Use
+    /// [`FrameBuilder::reversed`] for the other direction.
+    pub fn frame_builder(&self) -> FrameBuilder {
+        FrameBuilder::new(*self.dev1.mac_addr(), *self.dev2.mac_addr())
+    }
+}
@@ -0,0 +1,125 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::ready;
+use futures::sink::Sink;
+use futures::stream::Stream;
+
+use crate::{PacketSocket, Result, VethError, VethLink};
+
+/// Large enough for any frame on a link with the maximum veth MTU.
+const MAX_FRAME_LEN: usize = 65536 + 14;
+
+/// A veth end as an async channel of Ethernet frames: a [`Stream`] of the frames arriving at
+/// the link and a [`Sink`] of frames to transmit out of it.
+///
+/// Must be polled from within a tokio runtime.
+#[derive(Debug)]
+pub struct FrameStream {
+    socket: PacketSocket,
+    buf: Box<[u8]>,
+    pending: Option<Bytes>,
+    error: Option<VethError>,
+}
+
+impl FrameStream {
+    pub fn new(socket: PacketSocket) -> Self {
+        Self {
+            socket,
+            buf: vec![0; MAX_FRAME_LEN].into_boxed_slice(),
+            pending: None,
+            error: None,
+        }
+    }
+
+    /// The error that ended the stream, if it ended because receiving failed.
+    pub fn take_error(&mut self) -> Option<VethError> {
+        self.error.take()
+    }
+
+    pub fn socket(&self) -> &PacketSocket {
+        &self.socket
+    }
+}
+
+impl From<PacketSocket> for FrameStream {
+    fn from(socket: PacketSocket) -> Self {
+        Self::new(socket)
+    }
+}
+
+impl Stream for FrameStream {
+    type Item = Bytes;
+
+    /// Yields each frame arriving at the link. The stream only ends if receiving fails; the
+    /// error is then available from [`FrameStream::take_error`].
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Bytes>> {
+        let this = self.get_mut();
+        if this.error.is_some() {
+            return Poll::Ready(None);
+        }
+        let res = poll_recv(&this.socket, &mut this.buf, cx);
+        match ready!(res) {
+            Ok(len) => Poll::Ready(Some(Bytes::copy_from_slice(&this.buf[..len]))),
+            Err(e) => {
+                this.error = Some(e);
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+fn poll_recv(socket: &PacketSocket, buf: &mut [u8], cx: &mut Context<'_>) -> Poll<Result<usize>> {
+    let reactor = socket.reactor()?;
+    loop {
+        let mut guard = ready!(reactor.poll_read_ready(cx))?;
+        if let Ok(res) = guard.try_io(|_| socket.try_recv(buf)) {
+            return Poll::Ready(Ok(res?));
+        }
+    }
+}
+
+fn poll_send(socket: &PacketSocket, frame: &[u8], cx: &mut Context<'_>) -> Poll<Result<()>> {
+    let reactor = socket.reactor()?;
+    loop {
+        let mut guard = ready!(reactor.poll_write_ready(cx))?;
+        if let Ok(res) = guard.try_io(|_| socket.try_send(frame)) {
+            return Poll::Ready(res.map(|_| ()).map_err(VethError::from));
+        }
+    }
+}
+
+impl Sink<Bytes> for FrameStream {
+    type Error = VethError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, frame: Bytes) -> Result<()> {
+        self.get_mut().pending = Some(frame);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        if let Some(frame) = &this.pending {
+            let res = ready!(poll_send(&this.socket, frame, cx));
+            this.pending = None;
+            res?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl VethLink {
+    /// Opens a [`FrameStream`] on this link. See [`VethLink::packet_socket`].
+    pub fn frame_stream(&self) -> Result<FrameStream> {
+        Ok(FrameStream::new(self.packet_socket()?))
+    }
+}
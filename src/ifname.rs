@@ -0,0 +1,78 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{Result, VethError};
+
+/// Maximum interface name length in bytes, excluding the trailing NUL.
+pub(crate) const IFNAMSIZ: usize = 15;
+
+/// Hex digits of randomness in a generated name; fewer than this makes collisions likely.
+const MIN_TOKEN_LEN: usize = 4;
+const MAX_TOKEN_LEN: usize = 8;
+
+/// Checks `name` against the rules the kernel applies in `dev_valid_name`, plus `:`, which
+/// the kernel accepts but reserves for legacy alias interfaces.
+pub(crate) fn validate(name: &str) -> Result<()> {
+    let invalid = |reason: &str| {
+        Err(VethError::InvalidName {
+            name: name.into(),
+            reason: reason.into(),
+        })
+    };
+
+    if name.is_empty() {
+        return invalid("name is empty");
+    }
+    if name.len() > IFNAMSIZ {
+        return invalid(&format!("name is longer than {} bytes", IFNAMSIZ));
+    }
+    if name == "." || name == ".." {
+        return invalid("name is reserved");
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| *c == '/' || *c == ':' || c.is_whitespace())
+    {
+        return invalid(&format!("name contains {:?}", c));
+    }
+    Ok(())
+}
+
+/// Checks that names generated from `prefix` fit in IFNAMSIZ with enough room to be unique.
+pub(crate) fn check_prefix(prefix: &str) -> Result<()> {
+    if !prefix.is_empty() && prefix != "." && prefix != ".." {
+        validate(prefix)?;
+    }
+    // One byte is reserved for the end suffix.
+    if prefix.len() + MIN_TOKEN_LEN + 1 > IFNAMSIZ {
+        return Err(VethError::InvalidName {
+            name: prefix.into(),
+            reason: format!(
+                "prefix must be at most {} bytes",
+                IFNAMSIZ - MIN_TOKEN_LEN - 1
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Generates a fresh pair of names `<prefix><token>0` and `<prefix><token>1`.
+///
+/// The token mixes the PID, a per-process counter and a randomly seeded hasher, so parallel
+/// threads and processes are unlikely to collide; callers still retry on `EEXIST`.
+pub(crate) fn generate(prefix: &str) -> (String, String) {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    let token_len = (IFNAMSIZ - prefix.len() - 1).min(MAX_TOKEN_LEN);
+    let token = format!("{:016x}", hasher.finish());
+    let token = &token[..token_len];
+
+    (
+        format!("{}{}0", prefix, token),
+        format!("{}{}1", prefix, token),
+    )
+}
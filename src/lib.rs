@@ -1,20 +1,185 @@
 #![allow(unused)]
 
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
 use futures::stream::TryStreamExt;
 use mac_address::mac_address_by_name;
+use netlink_packet_route::link::{InfoData, InfoVeth, LinkAttribute, LinkInfo, LinkMessage};
 use rtnetlink::Handle;
 use tokio::task::JoinHandle;
 
+mod addr;
+mod capture;
+mod cleanup;
+mod error;
+mod events;
+mod expect;
+mod frame;
+mod frames;
+mod ifname;
+mod neigh;
+mod netns;
+mod packet;
+mod pcap;
+mod replay;
+mod route;
+mod state;
+mod stats;
+mod xdp;
+pub use addr::LinkAddress;
+pub use capture::PcapCapture;
+pub use cleanup::{cleanup_stale, cleanup_stale_matching, StaleFilter};
+pub use error::{Result, VethError};
+pub use events::{LinkEvent, LinkEventKind, LinkEvents};
+pub use expect::FrameMatcher;
+pub use frame::{FrameBuilder, TcpFlags};
+pub use frames::FrameStream;
+use netns::{find_named_netns, new_connection_in};
+pub use netns::{NetNs, NetnsId};
+pub use packet::PacketSocket;
+pub use replay::ReplayTiming;
+pub use route::LinkRoute;
+pub use stats::LinkStats;
+pub use xdp::{XdpAttachment, XdpMode};
+
 #[derive(Debug)]
 pub struct VethPair {
-    link_handle: Handle,
-    join_handle: JoinHandle<()>,
-    rt: tokio::runtime::Runtime,
+    join_handles: Vec<JoinHandle<()>>,
+    rt: Option<tokio::runtime::Runtime>,
     dev1: VethLink,
     dev2: VethLink,
+    delete_on_drop: bool,
 }
 
 impl VethPair {
+    /// Creates a veth pair on the caller's tokio runtime.
+    ///
+    /// Unlike [`add_veth_link`], this does not build its own runtime, so it can be
+    /// used from within `#[tokio::test]` or an application runtime.
+    pub async fn create(veth_config: &VethConfig) -> Result<Self> {
+        let (join_handles, dev1, dev2) = setup_veth_link(veth_config).await?;
+
+        Ok(Self {
+            join_handles,
+            rt: None,
+            dev1,
+            dev2,
+            delete_on_drop: true,
+        })
+    }
+
+    /// Deletes the veth pair, reporting any error instead of deferring to `Drop`.
+    pub async fn delete(mut self) -> Result<()> {
+        self.delete_on_drop = false;
+        delete_link(&self.dev1.handle, &self.dev1.ifname, self.dev1.index).await
+    }
+
+    /// Adopts an existing veth pair by the name of one of its ends, e.g. one set up by an
+    /// orchestrator. `dev1_ifname` must be in the caller's namespace; its peer becomes `dev2`
+    /// and may live in another namespace, provided that one is named under `/run/netns`.
+    ///
+    /// The pair is only deleted when dropped if `delete_on_drop` is set.
+    pub async fn open(dev1_ifname: &str, delete_on_drop: bool) -> Result<Self> {
+        let mut join_handles = Vec::new();
+        let res = async {
+            let (handle, join_handle) = new_connection_in(None)?;
+            join_handles.push(join_handle);
+            let index = get_link_index(&handle, dev1_ifname).await?;
+            let message = get_link(&handle, dev1_ifname, index).await?;
+            if !cleanup::is_veth(&message) {
+                return Err(VethError::InvalidArgument(format!(
+                    "{} is not a veth device",
+                    dev1_ifname
+                )));
+            }
+
+            let mut peer_index = None;
+            let mut peer_nsid = None;
+            for attr in &message.attributes {
+                match attr {
+                    LinkAttribute::Link(index) => peer_index = Some(*index),
+                    LinkAttribute::NetnsId(nsid) => peer_nsid = Some(*nsid),
+                    _ => {}
+                }
+            }
+            let dev1 = veth_link(dev1_ifname, None, handle.clone(), &message)?;
+
+            let peer_netns = match peer_nsid {
+                None => None,
+                Some(nsid) => Some(find_named_netns(&handle, nsid).await?.ok_or_else(|| {
+                    VethError::InvalidArgument(format!(
+                        "peer of {} is in a namespace not found under /run/netns",
+                        dev1_ifname
+                    ))
+                })?),
+            };
+            let (peer_handle, join_handle) = new_connection_in(peer_netns.as_ref())?;
+            join_handles.push(join_handle);
+            let peer_index =
+                peer_index.ok_or_else(|| VethError::LinkVanished(dev1_ifname.into()))?;
+            let peer_message = get_link(&peer_handle, dev1_ifname, peer_index).await?;
+            let peer_ifname = peer_message
+                .attributes
+                .iter()
+                .find_map(|attr| match attr {
+                    LinkAttribute::IfName(name) => Some(name.clone()),
+                    _ => None,
+                })
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "no name for peer interface")
+                })?;
+            let dev2 = veth_link(&peer_ifname, peer_netns, peer_handle, &peer_message)?;
+            Ok((dev1, dev2))
+        }
+        .await;
+
+        match res {
+            Ok((dev1, dev2)) => Ok(Self {
+                join_handles,
+                rt: None,
+                dev1,
+                dev2,
+                delete_on_drop,
+            }),
+            Err(e) => {
+                for join_handle in &join_handles {
+                    join_handle.abort();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Keeps the pair on the system when this value is dropped, e.g. to inspect it after a
+    /// failing test. It has to be removed by hand with `ip link del`.
+    pub fn into_persistent(mut self) -> Self {
+        self.delete_on_drop = false;
+        self
+    }
+
+    fn delete_blocking(&mut self) -> Result<()> {
+        self.delete_on_drop = false;
+        match &self.rt {
+            Some(rt) => rt.block_on(async {
+                delete_link(&self.dev1.handle, &self.dev1.ifname, self.dev1.index).await
+            }),
+            None => {
+                // We may be running inside the caller's runtime, where blocking on the
+                // connection task would deadlock, so tear down from a separate thread.
+                let netns = self.dev1.pinned_netns.clone();
+                let ifname = self.dev1.ifname.clone();
+                let index = self.dev1.index;
+                std::thread::spawn(move || delete_link_blocking(netns, &ifname, index))
+                    .join()
+                    .unwrap_or_else(|_| Err(io::Error::other("teardown thread panicked").into()))
+            }
+        }
+    }
+
     pub fn dev1(&self) -> &VethLink {
         &self.dev1
     }
@@ -29,6 +194,12 @@ pub struct VethLink {
     ifname: String,
     index: u32,
     mac_addr: [u8; 6],
+    num_tx_queues: u32,
+    num_rx_queues: u32,
+    netns: Option<NetnsId>,
+    /// `netns` pinned when the link was opened, used whenever the namespace is entered again.
+    pinned_netns: Option<NetnsId>,
+    handle: Handle,
 }
 
 impl VethLink {
@@ -43,156 +214,1659 @@ impl VethLink {
     pub fn mac_addr(&self) -> &[u8; 6] {
         &self.mac_addr
     }
+
+    pub fn num_tx_queues(&self) -> u32 {
+        self.num_tx_queues
+    }
+
+    pub fn num_rx_queues(&self) -> u32 {
+        self.num_rx_queues
+    }
+
+    /// The namespace this end was placed in, or `None` if it lives in the caller's namespace.
+    pub fn netns(&self) -> Option<&NetnsId> {
+        self.netns.as_ref()
+    }
 }
 
 #[derive(Debug)]
 pub struct VethConfig {
-    dev1_ifname: String,
-    dev2_ifname: String,
+    dev1: VethEndConfig,
+    dev2: VethEndConfig,
+    name_prefix: Option<String>,
+}
+
+/// Settings for one end of the pair; unset attributes are left to the kernel's defaults.
+#[derive(Debug, Default)]
+struct VethEndConfig {
+    ifname: String,
+    netns: Option<NetnsId>,
+    addresses: Vec<LinkAddress>,
+    mtu: Option<u32>,
+    mac_addr: Option<[u8; 6]>,
+    txqueuelen: Option<u32>,
+    alias: Option<String>,
+    group: Option<u32>,
+    num_tx_queues: Option<u32>,
+    num_rx_queues: Option<u32>,
+}
+
+impl VethEndConfig {
+    fn new(ifname: String) -> Self {
+        Self {
+            ifname,
+            ..Default::default()
+        }
+    }
+
+    /// Adds the attributes the kernel accepts at creation time to the link's message.
+    fn apply(&self, message: &mut LinkMessage, ns_file: Option<&File>) {
+        let attributes = &mut message.attributes;
+        if let Some(ns_file) = ns_file {
+            attributes.push(LinkAttribute::NetNsFd(ns_file.as_raw_fd()));
+        }
+        if let Some(mtu) = self.mtu {
+            attributes.push(LinkAttribute::Mtu(mtu));
+        }
+        if let Some(mac_addr) = self.mac_addr {
+            attributes.push(LinkAttribute::Address(mac_addr.to_vec()));
+        }
+        if let Some(txqueuelen) = self.txqueuelen {
+            attributes.push(LinkAttribute::TxQueueLen(txqueuelen));
+        }
+        if let Some(group) = self.group {
+            attributes.push(LinkAttribute::Group(group));
+        }
+        if let Some(num_tx_queues) = self.num_tx_queues {
+            attributes.push(LinkAttribute::NumTxQueues(num_tx_queues));
+        }
+        if let Some(num_rx_queues) = self.num_rx_queues {
+            attributes.push(LinkAttribute::NumRxQueues(num_rx_queues));
+        }
+    }
 }
 
 impl VethConfig {
-    pub fn new(dev1_ifname: String, dev2_ifname: String) -> Self {
+    /// Creates a configuration for a pair named `dev1_ifname` and `dev2_ifname`.
+    ///
+    /// Fails with [`VethError::InvalidName`] if either name would be rejected by the kernel,
+    /// or if both ends have the same name.
+    pub fn new(dev1_ifname: String, dev2_ifname: String) -> Result<Self> {
+        ifname::validate(&dev1_ifname)?;
+        ifname::validate(&dev2_ifname)?;
+        if dev1_ifname == dev2_ifname {
+            return Err(VethError::InvalidName {
+                name: dev2_ifname,
+                reason: "both ends have the same name".into(),
+            });
+        }
+
+        Ok(Self::new_unchecked(dev1_ifname, dev2_ifname))
+    }
+
+    fn new_unchecked(dev1_ifname: String, dev2_ifname: String) -> Self {
         Self {
-            dev1_ifname,
-            dev2_ifname,
+            dev1: VethEndConfig::new(dev1_ifname),
+            dev2: VethEndConfig::new(dev2_ifname),
+            name_prefix: None,
         }
     }
+
+    /// Lets the library pick collision-free names starting with `prefix`, so pairs can be
+    /// created from parallel tests. Read the chosen names back with [`VethLink::ifname`].
+    ///
+    /// Fails if `prefix` leaves too little of the 15-byte interface name for a unique suffix.
+    pub fn with_name_prefix(prefix: &str) -> Result<Self> {
+        ifname::check_prefix(prefix)?;
+        Ok(Self {
+            dev1: VethEndConfig::default(),
+            dev2: VethEndConfig::default(),
+            name_prefix: Some(prefix.into()),
+        })
+    }
+
+    /// Creates `dev1` directly in `netns` instead of the caller's namespace.
+    pub fn dev1_netns(mut self, netns: impl Into<NetnsId>) -> Self {
+        self.dev1.netns = Some(netns.into());
+        self
+    }
+
+    /// Creates `dev2` directly in `netns` instead of the caller's namespace.
+    pub fn dev2_netns(mut self, netns: impl Into<NetnsId>) -> Self {
+        self.dev2.netns = Some(netns.into());
+        self
+    }
+
+    /// Assigns `address` to `dev1` once it is up. May be called repeatedly.
+    pub fn dev1_address(mut self, address: LinkAddress) -> Self {
+        self.dev1.addresses.push(address);
+        self
+    }
+
+    /// Assigns `address` to `dev2` once it is up. May be called repeatedly.
+    pub fn dev2_address(mut self, address: LinkAddress) -> Self {
+        self.dev2.addresses.push(address);
+        self
+    }
+
+    pub fn dev1_mtu(mut self, mtu: u32) -> Self {
+        self.dev1.mtu = Some(mtu);
+        self
+    }
+
+    pub fn dev2_mtu(mut self, mtu: u32) -> Self {
+        self.dev2.mtu = Some(mtu);
+        self
+    }
+
+    /// Uses a fixed MAC address for `dev1` instead of a random one.
+    pub fn dev1_mac_addr(mut self, mac_addr: [u8; 6]) -> Self {
+        self.dev1.mac_addr = Some(mac_addr);
+        self
+    }
+
+    /// Uses a fixed MAC address for `dev2` instead of a random one.
+    pub fn dev2_mac_addr(mut self, mac_addr: [u8; 6]) -> Self {
+        self.dev2.mac_addr = Some(mac_addr);
+        self
+    }
+
+    pub fn dev1_txqueuelen(mut self, txqueuelen: u32) -> Self {
+        self.dev1.txqueuelen = Some(txqueuelen);
+        self
+    }
+
+    pub fn dev2_txqueuelen(mut self, txqueuelen: u32) -> Self {
+        self.dev2.txqueuelen = Some(txqueuelen);
+        self
+    }
+
+    /// Sets the `ifalias` description of `dev1`.
+    pub fn dev1_alias(mut self, alias: String) -> Self {
+        self.dev1.alias = Some(alias);
+        self
+    }
+
+    /// Sets the `ifalias` description of `dev2`.
+    pub fn dev2_alias(mut self, alias: String) -> Self {
+        self.dev2.alias = Some(alias);
+        self
+    }
+
+    /// Places `dev1` in link group `group` (equivalent to `ip link set IFNAME group GROUP`).
+    pub fn dev1_group(mut self, group: u32) -> Self {
+        self.dev1.group = Some(group);
+        self
+    }
+
+    /// Places `dev2` in link group `group` (equivalent to `ip link set IFNAME group GROUP`).
+    pub fn dev2_group(mut self, group: u32) -> Self {
+        self.dev2.group = Some(group);
+        self
+    }
+
+    /// Creates `dev1` with `num_tx_queues` TX and `num_rx_queues` RX queues instead of one
+    /// of each (equivalent to `ip link add ... numtxqueues N numrxqueues N`).
+    pub fn dev1_queues(mut self, num_tx_queues: u32, num_rx_queues: u32) -> Self {
+        self.dev1.num_tx_queues = Some(num_tx_queues);
+        self.dev1.num_rx_queues = Some(num_rx_queues);
+        self
+    }
+
+    /// Creates `dev2` with `num_tx_queues` TX and `num_rx_queues` RX queues instead of one
+    /// of each (equivalent to `ip link add ... numtxqueues N numrxqueues N`).
+    pub fn dev2_queues(mut self, num_tx_queues: u32, num_rx_queues: u32) -> Self {
+        self.dev2.num_tx_queues = Some(num_tx_queues);
+        self.dev2.num_rx_queues = Some(num_rx_queues);
+        self
+    }
 }
 
 impl Default for VethConfig {
     fn default() -> Self {
-        Self {
-            dev1_ifname: "veth0".into(),
-            dev2_ifname: "veth1".into(),
-        }
+        Self::new_unchecked("veth0".into(), "veth1".into())
     }
 }
 
 impl Drop for VethPair {
     fn drop(&mut self) {
-        self.rt
-            .block_on(async { delete_link(&self.link_handle, self.dev1.index).await })
-            .expect("failed to delete link");
+        if self.delete_on_drop {
+            match self.delete_blocking() {
+                // Already gone, e.g. removed by hand or together with its namespace.
+                Ok(()) | Err(VethError::LinkVanished(_)) => {}
+                Err(e) => eprintln!(
+                    "failed to delete veth pair {}/{}: {}",
+                    self.dev1.ifname, self.dev2.ifname, e
+                ),
+            }
+        }
+        for join_handle in &self.join_handles {
+            join_handle.abort();
+        }
     }
 }
 
-async fn delete_link(handle: &Handle, index: u32) -> anyhow::Result<()> {
-    Ok(handle.link().del(index).execute().await?)
+async fn delete_link(handle: &Handle, ifname: &str, index: u32) -> Result<()> {
+    handle
+        .link()
+        .del(index)
+        .execute()
+        .await
+        .map_err(|e| VethError::for_link(ifname, e))
+}
+
+fn delete_link_blocking(netns: Option<NetnsId>, ifname: &str, index: u32) -> Result<()> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    rt.block_on(async {
+        let (handle, _) = new_connection_in(netns.as_ref())?;
+        delete_link(&handle, ifname, index).await
+    })
 }
 
-async fn get_link_index(handle: &Handle, name: &str) -> anyhow::Result<u32> {
+async fn get_link_index(handle: &Handle, name: &str) -> Result<u32> {
     Ok(handle
         .link()
         .get()
         .match_name(name.into())
         .execute()
         .try_next()
-        .await?
-        .expect(format!("No link with name {} found", name).as_str())
+        .await
+        .map_err(|e| VethError::for_link(name, e))?
+        .ok_or_else(|| VethError::LinkVanished(name.into()))?
         .header
         .index)
 }
 
-async fn set_link_up(handle: &Handle, index: u32) -> anyhow::Result<()> {
-    Ok(handle.link().set(index).up().execute().await?)
+async fn get_link(handle: &Handle, ifname: &str, index: u32) -> Result<LinkMessage> {
+    handle
+        .link()
+        .get()
+        .match_index(index)
+        .execute()
+        .try_next()
+        .await
+        .map_err(|e| VethError::for_link(ifname, e))?
+        .ok_or_else(|| VethError::LinkVanished(ifname.into()))
+}
+
+fn link_mac(link: &LinkMessage) -> Result<[u8; 6]> {
+    link.attributes
+        .iter()
+        .find_map(|attr| match attr {
+            LinkAttribute::Address(addr) => <[u8; 6]>::try_from(addr.as_slice()).ok(),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "no mac addr for interface").into()
+        })
 }
 
-async fn setup_veth_link(
-    veth_config: &VethConfig,
-) -> anyhow::Result<(Handle, JoinHandle<()>, VethLink, VethLink)> {
-    let (connection, link_handle, _) =
-        rtnetlink::new_connection().expect("failed to create  rtnetlink connection");
-    let join_handle = tokio::spawn(connection);
+fn link_num_queues(link: &LinkMessage) -> (u32, u32) {
+    link.attributes
+        .iter()
+        .fold((1, 1), |(tx, rx), attr| match attr {
+            LinkAttribute::NumTxQueues(n) => (*n, rx),
+            LinkAttribute::NumRxQueues(n) => (tx, *n),
+            _ => (tx, rx),
+        })
+}
 
-    link_handle
+async fn set_link_up(handle: &Handle, ifname: &str, index: u32) -> Result<()> {
+    handle
         .link()
-        .add()
-        .veth(
-            veth_config.dev1_ifname.clone(),
-            veth_config.dev2_ifname.clone(),
-        )
+        .set(index)
+        .up()
         .execute()
-        .await?;
+        .await
+        .map_err(|e| VethError::for_link(ifname, e))
+}
 
-    let dev1_index = get_link_index(&link_handle, &veth_config.dev1_ifname).await.expect(
-            format!(
-                "Failed to retrieve index, this is not expected. Remove link manually: 'sudo ip link del {}'",
-                veth_config.dev1_ifname
-            )
-            .as_str(),
-        );
-    let dev2_index = get_link_index(&link_handle, &veth_config.dev2_ifname).await?;
+async fn set_link_down(handle: &Handle, ifname: &str, index: u32) -> Result<()> {
+    handle
+        .link()
+        .set(index)
+        .down()
+        .execute()
+        .await
+        .map_err(|e| VethError::for_link(ifname, e))
+}
 
-    set_link_up(&link_handle, dev1_index).await?;
-    set_link_up(&link_handle, dev2_index).await?;
+fn veth_peer_mut(message: &mut LinkMessage) -> Option<&mut LinkMessage> {
+    message.attributes.iter_mut().find_map(|attr| match attr {
+        LinkAttribute::LinkInfo(infos) => infos.iter_mut().find_map(|info| match info {
+            LinkInfo::Data(InfoData::Veth(InfoVeth::Peer(peer))) => Some(peer),
+            _ => None,
+        }),
+        _ => None,
+    })
+}
 
-    let mac1 = match mac_address_by_name(&veth_config.dev1_ifname) {
-        Ok(Some(ma)) => ma.bytes(),
-        Ok(None) => {
-            anyhow::bail!("no mac addr for interface");
-        }
-        Err(e) => {
-            eprintln!("{:?}", e);
-            anyhow::bail!("error retrieving mac addr");
-        }
-    };
+fn veth_link(
+    ifname: &str,
+    netns: Option<NetnsId>,
+    handle: Handle,
+    message: &LinkMessage,
+) -> Result<VethLink> {
+    let (num_tx_queues, num_rx_queues) = link_num_queues(message);
+    let pinned_netns = netns.as_ref().map(NetnsId::pin).transpose()?;
+    Ok(VethLink {
+        ifname: ifname.into(),
+        index: message.header.index,
+        mac_addr: link_mac(message)?,
+        num_tx_queues,
+        num_rx_queues,
+        netns,
+        pinned_netns,
+        handle,
+    })
+}
+
+/// Opens the end `ifname` of a freshly created pair and applies the rest of `config`,
+/// adding the connection task driving its handle to `join_handles`.
+async fn open_veth_link(
+    config: &VethEndConfig,
+    ifname: &str,
+    join_handles: &mut Vec<JoinHandle<()>>,
+) -> Result<VethLink> {
+    let (handle, join_handle) = new_connection_in(config.netns.as_ref())?;
+    join_handles.push(join_handle);
+
+    let index = get_link_index(&handle, ifname).await?;
+    // The kernel ignores IFLA_IFALIAS on RTM_NEWLINK, so it has to be set separately. Without
+    // an explicit alias, record the owning process for `cleanup_stale`.
+    let alias = config.alias.clone().unwrap_or_else(cleanup::owner_alias);
+    let mut request = handle.link().set(index);
+    request
+        .message_mut()
+        .attributes
+        .push(LinkAttribute::IfAlias(alias));
+    request
+        .execute()
+        .await
+        .map_err(|e| VethError::for_link(ifname, e))?;
+    set_link_up(&handle, ifname, index).await?;
+    let link_message = get_link(&handle, ifname, index).await?;
+    let link = veth_link(ifname, config.netns.clone(), handle, &link_message)?;
 
-    let mac2 = match mac_address_by_name(&veth_config.dev2_ifname) {
-        Ok(Some(ma)) => ma.bytes(),
-        Ok(None) => {
-            anyhow::bail!("no mac addr for interface");
+    for address in &config.addresses {
+        link.add_address(*address).await?;
+    }
+
+    Ok(link)
+}
+
+/// Deletes the link `ifname` in `netns` by name, for when no [`VethLink`] was opened for it.
+async fn delete_link_by_name(netns: Option<&NetnsId>, ifname: &str) -> Result<()> {
+    let (handle, join_handle) = new_connection_in(netns)?;
+    let res = async {
+        let index = get_link_index(&handle, ifname).await?;
+        delete_link(&handle, ifname, index).await
+    }
+    .await;
+    join_handle.abort();
+    res
+}
+
+/// How long setting up a pair waits for both ends to become operationally up.
+const OPER_UP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of fresh names tried before giving up when the library picks the names.
+const NAME_ATTEMPTS: usize = 8;
+
+async fn create_veth(
+    link_handle: &Handle,
+    veth_config: &VethConfig,
+    dev1_ifname: &str,
+    dev2_ifname: &str,
+) -> Result<()> {
+    let dev1_ns = veth_config
+        .dev1
+        .netns
+        .as_ref()
+        .map(NetnsId::open)
+        .transpose()?;
+    let dev2_ns = veth_config
+        .dev2
+        .netns
+        .as_ref()
+        .map(NetnsId::open)
+        .transpose()?;
+
+    let mut request = link_handle
+        .link()
+        .add()
+        .veth(dev1_ifname.into(), dev2_ifname.into());
+    // `veth()` names the outer message after the second device and the peer after the first.
+    veth_config
+        .dev2
+        .apply(request.message_mut(), dev2_ns.as_ref());
+    veth_config.dev1.apply(
+        veth_peer_mut(request.message_mut()).expect("veth request without peer"),
+        dev1_ns.as_ref(),
+    );
+
+    match request.execute().await {
+        Ok(()) => Ok(()),
+        Err(e) => Err(match VethError::for_link(dev1_ifname, e) {
+            // The kernel does not say which of the two names is taken.
+            VethError::AlreadyExists(_)
+                if get_link_index(link_handle, dev2_ifname).await.is_ok() =>
+            {
+                VethError::AlreadyExists(dev2_ifname.into())
+            }
+            err => err,
+        }),
+    }
+}
+
+async fn setup_veth_link(
+    veth_config: &VethConfig,
+) -> Result<(Vec<JoinHandle<()>>, VethLink, VethLink)> {
+    let (link_handle, join_handle) = new_connection_in(None)?;
+
+    let res = match &veth_config.name_prefix {
+        None => {
+            let (dev1_ifname, dev2_ifname) = (
+                veth_config.dev1.ifname.clone(),
+                veth_config.dev2.ifname.clone(),
+            );
+            create_veth(&link_handle, veth_config, &dev1_ifname, &dev2_ifname)
+                .await
+                .map(|_| (dev1_ifname, dev2_ifname))
         }
-        Err(e) => {
-            eprintln!("{:?}", e);
-            anyhow::bail!("error retrieving mac addr");
+        Some(prefix) => {
+            let mut attempt = 1;
+            loop {
+                let (dev1_ifname, dev2_ifname) = ifname::generate(prefix);
+                match create_veth(&link_handle, veth_config, &dev1_ifname, &dev2_ifname).await {
+                    Ok(()) => break Ok((dev1_ifname, dev2_ifname)),
+                    Err(VethError::AlreadyExists(_)) if attempt < NAME_ATTEMPTS => attempt += 1,
+                    Err(e) => break Err(e),
+                }
+            }
         }
     };
+    join_handle.abort();
+    let (dev1_ifname, dev2_ifname) = res?;
 
-    let dev1 = VethLink {
-        ifname: veth_config.dev1_ifname.clone(),
-        index: dev1_index,
-        mac_addr: mac1,
-    };
+    let mut join_handles = Vec::new();
+    let res = async {
+        let dev1 = open_veth_link(&veth_config.dev1, &dev1_ifname, &mut join_handles).await?;
+        let dev2 = open_veth_link(&veth_config.dev2, &dev2_ifname, &mut join_handles).await?;
 
-    let dev2 = VethLink {
-        ifname: veth_config.dev2_ifname.clone(),
-        index: dev2_index,
-        mac_addr: mac2,
-    };
+        // Admin-up returns before the kernel has brought the carrier up, and frames sent in
+        // between are dropped.
+        futures::future::try_join(
+            dev1.wait_oper_up(OPER_UP_TIMEOUT),
+            dev2.wait_oper_up(OPER_UP_TIMEOUT),
+        )
+        .await?;
+        Ok((dev1, dev2))
+    }
+    .await;
 
-    Ok((link_handle, join_handle, dev1, dev2))
+    match res {
+        Ok((dev1, dev2)) => Ok((join_handles, dev1, dev2)),
+        Err(e) => {
+            // The pair exists by now; left behind, it would make the next run with the same
+            // names fail with `AlreadyExists`.
+            let _ = delete_link_by_name(veth_config.dev1.netns.as_ref(), &dev1_ifname).await;
+            for join_handle in &join_handles {
+                join_handle.abort();
+            }
+            Err(e)
+        }
+    }
 }
 
-pub fn add_veth_link(veth_config: &VethConfig) -> anyhow::Result<VethPair> {
-    let rt = tokio::runtime::Runtime::new().expect("failed to build tokio runtime");
+/// Creates a veth pair, blocking the current thread until it is set up.
+///
+/// This builds a dedicated tokio runtime and must not be called from within one; async
+/// callers should use [`VethPair::create`] instead.
+pub fn add_veth_link(veth_config: &VethConfig) -> Result<VethPair> {
+    let rt = tokio::runtime::Runtime::new()?;
 
-    let (link_handle, join_handle, dev1, dev2) =
-        rt.block_on(async { setup_veth_link(veth_config).await })?;
+    let (join_handles, dev1, dev2) = rt.block_on(async { setup_veth_link(veth_config).await })?;
 
     Ok(VethPair {
-        link_handle,
-        join_handle,
-        rt,
+        join_handles,
+        rt: Some(rt),
         dev1,
         dev2,
+        delete_on_drop: true,
     })
 }
 
+/// Deletes a veth pair, blocking the current thread until it is gone.
+///
+/// Unlike dropping the pair, this reports failures, including the pair having already been
+/// removed ([`VethError::LinkVanished`]).
+pub fn del_veth_link(mut pair: VethPair) -> Result<()> {
+    pair.delete_blocking()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::sink::SinkExt;
+    use futures::stream::StreamExt;
+    use std::os::unix::ffi::OsStringExt;
 
     #[test]
     fn test_default_config() {
         let veth_config = VethConfig::default();
         let pair = add_veth_link(&veth_config).expect("failed to create veth pair");
-        assert_eq!(pair.dev1().ifname(), veth_config.dev1_ifname);
-        assert_eq!(pair.dev2().ifname(), veth_config.dev2_ifname);
+        assert_eq!(pair.dev1().ifname(), veth_config.dev1.ifname);
+        assert_eq!(pair.dev2().ifname(), veth_config.dev2.ifname);
 
         pair.dev1().index();
         pair.dev2().index();
         pair.dev1().mac_addr();
         pair.dev2().mac_addr();
     }
+
+    #[tokio::test]
+    async fn test_create_async() {
+        let veth_config = VethConfig::new("vasync0".into(), "vasync1".into()).unwrap();
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+        assert_eq!(pair.dev1().ifname(), "vasync0");
+        assert_eq!(pair.dev2().ifname(), "vasync1");
+
+        pair.delete().await.expect("failed to delete veth pair");
+    }
+
+    #[tokio::test]
+    async fn test_drop_async() {
+        let veth_config = VethConfig::new("vdrop0".into(), "vdrop1".into()).unwrap();
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+        drop(pair);
+        assert!(mac_address_by_name("vdrop0").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_del_veth_link() {
+        let pair = add_veth_link(&VethConfig::new("vdel0".into(), "vdel1".into()).unwrap())
+            .expect("failed to create veth pair");
+        del_veth_link(pair).expect("failed to delete veth pair");
+        assert!(mac_address_by_name("vdel0").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_drop_vanished_link() {
+        let pair = add_veth_link(&VethConfig::new("vgone0".into(), "vgone1".into()).unwrap())
+            .expect("failed to create veth pair");
+        let status = std::process::Command::new("ip")
+            .args(["link", "del", "vgone0"])
+            .status()
+            .expect("failed to run ip");
+        assert!(status.success());
+        drop(pair);
+    }
+
+    #[tokio::test]
+    async fn test_drop_after_netns_unmounted() {
+        let host = NetNs::new_named("veth-util-gone").expect("failed to create netns");
+        let veth_config = VethConfig::new("vnsgone0".into(), "vnsgone1".into())
+            .unwrap()
+            .dev1_netns(&host);
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+        // Unmounts the namespace, so it can no longer be entered by name.
+        drop(host);
+        drop(pair);
+        assert!(mac_address_by_name("vnsgone1").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_into_persistent() {
+        let pair = VethPair::create(&VethConfig::new("vkeep0".into(), "vkeep1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        drop(pair.into_persistent());
+        assert!(mac_address_by_name("vkeep0").unwrap().is_some());
+
+        std::process::Command::new("ip")
+            .args(["link", "del", "vkeep0"])
+            .status()
+            .expect("failed to run ip");
+    }
+
+    #[tokio::test]
+    async fn test_dev2_in_named_netns() {
+        let status = std::process::Command::new("ip")
+            .args(["netns", "add", "veth-util-test"])
+            .status()
+            .expect("failed to run ip");
+        assert!(status.success());
+
+        let netns = NetnsId::Named("veth-util-test".into());
+        let veth_config = VethConfig::new("vns0".into(), "vns1".into())
+            .unwrap()
+            .dev2_netns(netns.clone());
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+        assert_eq!(pair.dev1().netns(), None);
+        assert_eq!(pair.dev2().netns(), Some(&netns));
+        assert!(mac_address_by_name("vns0").unwrap().is_some());
+        assert!(mac_address_by_name("vns1").unwrap().is_none());
+        assert_ne!(pair.dev2().mac_addr(), &[0; 6]);
+        drop(pair);
+
+        std::process::Command::new("ip")
+            .args(["netns", "del", "veth-util-test"])
+            .status()
+            .expect("failed to run ip");
+    }
+
+    #[tokio::test]
+    async fn test_two_host_netns() {
+        let host1 = NetNs::new().expect("failed to create netns");
+        let host2 = NetNs::new_named("veth-util-host2").expect("failed to create netns");
+        assert_eq!(host2.name(), Some("veth-util-host2"));
+
+        let veth_config = VethConfig::new("vhost0".into(), "vhost1".into())
+            .unwrap()
+            .dev1_netns(&host1)
+            .dev2_netns(&host2);
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+        assert!(mac_address_by_name("vhost0").unwrap().is_none());
+
+        let mac1 = host1
+            .run(|| mac_address_by_name("vhost0").unwrap())
+            .expect("failed to run in netns");
+        assert_eq!(mac1.map(|ma| ma.bytes()), Some(*pair.dev1().mac_addr()));
+
+        let index2 = host2
+            .spawn_in(|| async {
+                let (handle, _) = new_connection_in(None)?;
+                get_link_index(&handle, "vhost1").await
+            })
+            .expect("failed to spawn in netns")
+            .await
+            .expect("netns task failed")
+            .expect("failed to look up link");
+        assert_eq!(index2, pair.dev2().index());
+
+        drop(pair);
+        drop(host2);
+        assert!(!std::path::Path::new("/run/netns/veth-util-host2").exists());
+    }
+
+    #[tokio::test]
+    async fn test_netns_fd_outlives_netns() {
+        let host = NetNs::new().expect("failed to create netns");
+        let netns = NetnsId::from(&host);
+        let veth_config = VethConfig::new("vfd0".into(), "vfd1".into())
+            .unwrap()
+            .dev1_netns(&host);
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+
+        // Reuse the descriptor number the namespace was opened with, if it were closed.
+        drop(host);
+        let _files: Vec<_> = (0..4)
+            .map(|_| std::fs::File::open("/dev/null").unwrap())
+            .collect();
+        pair.dev1().set_down().await.unwrap();
+        pair.delete().await.expect("failed to delete veth pair");
+
+        let remaining =
+            netns::run_in(Some(&netns), || Ok(mac_address_by_name("vfd0").unwrap())).unwrap();
+        assert!(remaining.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_addresses() {
+        let v4: LinkAddress = "10.200.0.1/24".parse().unwrap();
+        let v6: LinkAddress = "fd00:200::1/64".parse().unwrap();
+        let veth_config = VethConfig::new("vaddr0".into(), "vaddr1".into())
+            .unwrap()
+            .dev1_address(v4)
+            .dev1_address(v6.nodad());
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+
+        let addresses = pair.dev1().addresses().await.unwrap();
+        assert!(addresses
+            .iter()
+            .any(|a| a.addr() == v4.addr() && a.prefix_len() == 24));
+        assert!(addresses
+            .iter()
+            .any(|a| a.addr() == v6.addr() && a.prefix_len() == 64));
+
+        let extra: LinkAddress = "10.201.0.2/16".parse().unwrap();
+        pair.dev2()
+            .add_address(extra.noprefixroute())
+            .await
+            .unwrap();
+        let err = pair
+            .dev2()
+            .add_address(extra.noprefixroute())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, VethError::Netlink(_)));
+        assert_eq!(err.errno(), Some(nix::errno::Errno::EEXIST as i32));
+        assert!(pair
+            .dev2()
+            .addresses()
+            .await
+            .unwrap()
+            .contains(&extra.noprefixroute()));
+        pair.dev2().remove_address(extra).await.unwrap();
+        assert!(!pair
+            .dev2()
+            .addresses()
+            .await
+            .unwrap()
+            .iter()
+            .any(|a| a.addr() == extra.addr()));
+    }
+
+    #[tokio::test]
+    async fn test_routes() {
+        let host1 = NetNs::new().expect("failed to create netns");
+        let host2 = NetNs::new().expect("failed to create netns");
+        let veth_config = VethConfig::new("vroute0".into(), "vroute1".into())
+            .unwrap()
+            .dev1_netns(&host1)
+            .dev2_netns(&host2)
+            .dev1_address("10.210.0.1/24".parse().unwrap())
+            .dev2_address("10.210.0.2/24".parse().unwrap());
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+
+        let default = LinkRoute::default_via("10.210.0.2".parse().unwrap());
+        let prefix = LinkRoute::prefix("10.211.0.0".parse().unwrap(), 16)
+            .unwrap()
+            .metric(50)
+            .table(100);
+        pair.dev1().add_route(&default).await.unwrap();
+        pair.dev1().add_route(&prefix).await.unwrap();
+
+        let show_routes = || {
+            host1
+                .run(|| {
+                    std::process::Command::new("ip")
+                        .args(["route", "show", "table", "all"])
+                        .output()
+                        .expect("failed to run ip")
+                })
+                .map(|output| String::from_utf8(output.stdout).unwrap())
+                .unwrap()
+        };
+        let routes = show_routes();
+        assert!(routes.contains("default via 10.210.0.2 dev vroute0"));
+        assert!(routes.contains("10.211.0.0/16 dev vroute0 table 100"));
+        assert!(routes.contains("metric 50"));
+
+        pair.dev1().remove_route(&default).await.unwrap();
+        pair.dev1().remove_route(&prefix).await.unwrap();
+        let routes = show_routes();
+        assert!(!routes.contains("default via"));
+        assert!(!routes.contains("10.211.0.0/16"));
+
+        assert!(LinkRoute::prefix("fd00::".parse().unwrap(), 64)
+            .unwrap()
+            .via("10.210.0.2".parse().unwrap())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_static_neighbors() {
+        let veth_config = VethConfig::new("vneigh0".into(), "vneigh1".into())
+            .unwrap()
+            .dev1_address("10.220.0.1/24".parse().unwrap())
+            .dev2_address("10.220.0.2/24".parse().unwrap());
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+        pair.install_static_neighbors().await.unwrap();
+
+        let show_neighbors = |ifname: &str| {
+            let output = std::process::Command::new("ip")
+                .args(["neigh", "show", "dev", ifname])
+                .output()
+                .expect("failed to run ip");
+            String::from_utf8(output.stdout).unwrap()
+        };
+        let format_mac = |mac: &[u8; 6]| {
+            mac.iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":")
+        };
+
+        let neighbors = show_neighbors("vneigh0");
+        let expected = format!(
+            "10.220.0.2 lladdr {} PERMANENT",
+            format_mac(pair.dev2().mac_addr())
+        );
+        assert!(neighbors.contains(&expected), "{}", neighbors);
+        let neighbors = show_neighbors("vneigh1");
+        let expected = format!(
+            "10.220.0.1 lladdr {} PERMANENT",
+            format_mac(pair.dev1().mac_addr())
+        );
+        assert!(neighbors.contains(&expected), "{}", neighbors);
+
+        pair.dev1()
+            .remove_neighbor("10.220.0.2".parse().unwrap())
+            .await
+            .unwrap();
+        assert!(!show_neighbors("vneigh0").contains("10.220.0.2"));
+    }
+
+    #[tokio::test]
+    async fn test_link_attributes() {
+        let mac = [0x02, 0x00, 0x00, 0x00, 0x07, 0x01];
+        let veth_config = VethConfig::new("vattr0".into(), "vattr1".into())
+            .unwrap()
+            .dev1_mtu(9000)
+            .dev1_mac_addr(mac)
+            .dev1_txqueuelen(2000)
+            .dev1_alias("veth-util test".into())
+            .dev1_group(7)
+            .dev2_mtu(9000);
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+        assert_eq!(pair.dev1().mac_addr(), &mac);
+
+        let output = std::process::Command::new("ip")
+            .args(["-d", "link", "show", "dev", "vattr0"])
+            .output()
+            .expect("failed to run ip");
+        let details = String::from_utf8(output.stdout).unwrap();
+        assert!(details.contains("mtu 9000"), "{}", details);
+        assert!(details.contains("qlen 2000"), "{}", details);
+        assert!(details.contains("group 7"), "{}", details);
+        assert!(details.contains("alias veth-util test"), "{}", details);
+        assert!(
+            details.contains("link/ether 02:00:00:00:07:01"),
+            "{}",
+            details
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multi_queue() {
+        let veth_config = VethConfig::new("vmq0".into(), "vmq1".into())
+            .unwrap()
+            .dev1_queues(4, 4)
+            .dev2_queues(2, 8);
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+        assert_eq!(pair.dev1().num_tx_queues(), 4);
+        assert_eq!(pair.dev1().num_rx_queues(), 4);
+        assert_eq!(pair.dev2().num_tx_queues(), 2);
+        assert_eq!(pair.dev2().num_rx_queues(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_already_exists() {
+        let pair = VethPair::create(&VethConfig::new("vexist0".into(), "vexist1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+
+        let err = VethPair::create(&VethConfig::new("vexist2".into(), "vexist1".into()).unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, VethError::AlreadyExists(name) if name == "vexist1"));
+        assert_eq!(err.errno(), Some(nix::errno::Errno::EEXIST as i32));
+
+        let err = VethPair::create(&VethConfig::new("vexist0".into(), "vexist3".into()).unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, VethError::AlreadyExists(name) if name == "vexist0"));
+    }
+
+    #[tokio::test]
+    async fn test_setup_failure_deletes_pair() {
+        // Longer than IFALIASZ, so setting it fails after the pair has been created.
+        let veth_config = VethConfig::new("vfail0".into(), "vfail1".into())
+            .unwrap()
+            .dev1_alias("x".repeat(300));
+        assert!(VethPair::create(&veth_config).await.is_err());
+        assert!(mac_address_by_name("vfail0").unwrap().is_none());
+        assert!(mac_address_by_name("vfail1").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_name_prefix() {
+        let veth_config = VethConfig::with_name_prefix("vtest").unwrap();
+        let (pair1, pair2) = tokio::join!(
+            VethPair::create(&veth_config),
+            VethPair::create(&veth_config)
+        );
+        let (pair1, pair2) = (pair1.unwrap(), pair2.unwrap());
+
+        let names = [
+            pair1.dev1().ifname(),
+            pair1.dev2().ifname(),
+            pair2.dev1().ifname(),
+            pair2.dev2().ifname(),
+        ];
+        for (i, name) in names.iter().enumerate() {
+            assert!(name.starts_with("vtest"));
+            assert!(name.len() <= 15);
+            assert!(!names[..i].contains(name));
+        }
+
+        assert!(matches!(
+            VethConfig::with_name_prefix("much-too-long"),
+            Err(VethError::InvalidName { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_stale() {
+        let pair = VethPair::create(&VethConfig::new("vstale0".into(), "vstale1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        drop(pair.into_persistent());
+
+        let deleted = cleanup_stale("vstale").await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert!(deleted[0].starts_with("vstale"));
+        assert!(mac_address_by_name("vstale0").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_dead_owner() {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+
+        let dead = VethConfig::new("vdead0".into(), "vdead1".into())
+            .unwrap()
+            .dev1_alias(format!("veth-util-rs pid={}", dead_pid));
+        let dead = VethPair::create(&dead).await.unwrap().into_persistent();
+        let live = VethPair::create(&VethConfig::new("vlive0".into(), "vlive1".into()).unwrap())
+            .await
+            .unwrap();
+
+        let deleted = cleanup_stale_matching(&StaleFilter::owned().dead_owner_only())
+            .await
+            .unwrap();
+        assert_eq!(deleted, vec!["vdead0".to_string()]);
+        assert!(mac_address_by_name("vlive0").unwrap().is_some());
+        drop(dead);
+    }
+
+    #[tokio::test]
+    async fn test_open_existing() {
+        let host = NetNs::new_named("veth-util-open").expect("failed to create netns");
+        let veth_config = VethConfig::new("vopen0".into(), "vopen1".into())
+            .unwrap()
+            .dev2_netns(&host);
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+
+        let opened = VethPair::open("vopen0", false)
+            .await
+            .expect("failed to open veth pair");
+        assert_eq!(opened.dev1().index(), pair.dev1().index());
+        assert_eq!(opened.dev1().mac_addr(), pair.dev1().mac_addr());
+        assert_eq!(opened.dev2().ifname(), "vopen1");
+        assert_eq!(opened.dev2().index(), pair.dev2().index());
+        assert_eq!(opened.dev2().mac_addr(), pair.dev2().mac_addr());
+        assert_eq!(opened.dev2().netns(), Some(&NetnsId::from(&host)));
+        drop(opened);
+        assert!(mac_address_by_name("vopen0").unwrap().is_some());
+
+        let local = VethPair::create(&VethConfig::new("vopen2".into(), "vopen3".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        let opened = VethPair::open("vopen3", true)
+            .await
+            .expect("failed to open veth pair");
+        assert_eq!(opened.dev2().ifname(), "vopen2");
+        assert_eq!(opened.dev2().netns(), None);
+        drop(local.into_persistent());
+        drop(opened);
+        assert!(mac_address_by_name("vopen2").unwrap().is_none());
+
+        assert!(matches!(
+            VethPair::open("lo", false).await,
+            Err(VethError::InvalidArgument(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wait_oper_up() {
+        let pair = VethPair::create(&VethConfig::new("voper0".into(), "voper1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        let operstate = std::fs::read_to_string("/sys/class/net/voper0/operstate").unwrap();
+        assert_eq!(operstate.trim(), "up");
+
+        let status = std::process::Command::new("ip")
+            .args(["link", "set", "voper1", "down"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(matches!(
+            pair.dev1().wait_oper_up(Duration::from_millis(100)).await,
+            Err(VethError::Timeout(_))
+        ));
+
+        let wait = pair.dev1().wait_oper_up(Duration::from_secs(5));
+        let status = std::process::Command::new("ip")
+            .args(["link", "set", "voper1", "up"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        wait.await.expect("link did not come up");
+    }
+
+    async fn next_event(events: &mut LinkEvents, ifname: &str, kind: LinkEventKind) {
+        let wanted = |event: &LinkEvent| event.ifname() == ifname && *event.kind() == kind;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(event) = events.next().await {
+                if wanted(&event) {
+                    return;
+                }
+            }
+            panic!("event stream ended before {} {:?}", ifname, kind);
+        })
+        .await
+        .unwrap_or_else(|_| panic!("no {:?} event for {}", kind, ifname));
+    }
+
+    #[tokio::test]
+    async fn test_link_events() {
+        let host = NetNs::new_named("veth-util-events").expect("failed to create netns");
+        let pair = VethPair::create(&VethConfig::new("vev0".into(), "vev1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        let mut events = pair.events().await.expect("failed to subscribe");
+        let ip = |args: &[&str]| {
+            let status = std::process::Command::new("ip")
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+
+        ip(&["link", "set", "vev1", "down"]);
+        next_event(&mut events, "vev1", LinkEventKind::Down).await;
+        next_event(&mut events, "vev0", LinkEventKind::CarrierDown).await;
+        ip(&["link", "set", "vev1", "up"]);
+        next_event(&mut events, "vev0", LinkEventKind::CarrierUp).await;
+
+        ip(&["link", "set", "vev0", "mtu", "1400"]);
+        next_event(&mut events, "vev0", LinkEventKind::MtuChanged(1400)).await;
+
+        let address: LinkAddress = "10.66.0.1/24".parse().unwrap();
+        pair.dev1().add_address(address).await.unwrap();
+        next_event(&mut events, "vev0", LinkEventKind::AddressAdded(address)).await;
+
+        ip(&["link", "set", "vev1", "netns", "veth-util-events"]);
+        next_event(&mut events, "vev1", LinkEventKind::MovedNetns).await;
+        ip(&["link", "del", "vev0"]);
+        next_event(&mut events, "vev0", LinkEventKind::Deleted).await;
+        assert!(events.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_carrier_control() {
+        let pair = VethPair::create(&VethConfig::new("vfault0".into(), "vfault1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        let sysfs = |ifname: &str, attr: &str| {
+            std::fs::read_to_string(format!("/sys/class/net/{}/{}", ifname, attr))
+                .unwrap()
+                .trim()
+                .to_string()
+        };
+
+        pair.set_dev1_carrier(false).await.unwrap();
+        assert_eq!(sysfs("vfault0", "carrier"), "0");
+        assert_eq!(sysfs("vfault0", "operstate"), "lowerlayerdown");
+        pair.set_dev1_carrier(true).await.unwrap();
+        pair.dev1()
+            .wait_oper_up(Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        pair.dev1().set_down().await.unwrap();
+        assert_eq!(sysfs("vfault0", "operstate"), "down");
+        pair.dev1().set_up().await.unwrap();
+        pair.dev2()
+            .wait_oper_up(Duration::from_secs(5))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let host = NetNs::new().expect("failed to create netns");
+        let veth_config = VethConfig::new("vstats0".into(), "vstats1".into())
+            .unwrap()
+            .dev1_address("10.77.0.1/24".parse().unwrap())
+            .dev2_address("10.77.0.2/24".parse().unwrap())
+            .dev2_netns(&host);
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+        pair.install_static_neighbors().await.unwrap();
+        // Keep IPv6 autoconfiguration traffic out of the counts.
+        std::fs::write("/proc/sys/net/ipv6/conf/vstats0/disable_ipv6", "1").unwrap();
+        host.run(|| std::fs::write("/proc/sys/net/ipv6/conf/vstats1/disable_ipv6", "1"))
+            .unwrap()
+            .unwrap();
+
+        let tx_before = pair.dev1().stats().await.unwrap();
+        let rx_before = pair.dev2().stats().await.unwrap();
+        let socket = std::net::UdpSocket::bind("10.77.0.1:0").unwrap();
+        for _ in 0..5 {
+            socket.send_to(b"hello", "10.77.0.2:9").unwrap();
+        }
+        let tx = pair.dev1().stats().await.unwrap().since(&tx_before);
+        let rx = pair.dev2().stats().await.unwrap().since(&rx_before);
+
+        assert_eq!(tx.tx_packets, 5);
+        assert_eq!(rx.rx_packets, 5);
+        // Ethernet, IPv4 and UDP headers plus the payload.
+        assert_eq!(tx.tx_bytes, 5 * (14 + 20 + 8 + 5));
+        assert_eq!(rx.rx_bytes, tx.tx_bytes);
+    }
+
+    #[tokio::test]
+    async fn test_packet_socket() {
+        let pair = VethPair::create(&VethConfig::new("vpkt0".into(), "vpkt1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        let tx = pair.dev1().packet_socket().expect("failed to open socket");
+        let rx = pair.dev2().packet_socket().expect("failed to open socket");
+        rx.set_promiscuous(true).unwrap();
+        let flags = std::fs::read_to_string("/sys/class/net/vpkt1/flags").unwrap();
+        let flags = u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).unwrap();
+        assert_ne!(flags & libc::IFF_PROMISC as u32, 0);
+
+        // A frame with a local experimental ethertype, so it cannot be confused with the
+        // IPv6 housekeeping traffic on a fresh link.
+        let mut frame = Vec::new();
+        frame.extend_from_slice(pair.dev2().mac_addr());
+        frame.extend_from_slice(pair.dev1().mac_addr());
+        frame.extend_from_slice(&[0x88, 0xb5]);
+        frame.extend_from_slice(&[0xab; 46]);
+        let is_test_frame = |buf: &[u8]| buf[12..14] == [0x88, 0xb5];
+
+        assert_eq!(tx.send(&frame).unwrap(), frame.len());
+        let mut buf = [0u8; 2048];
+        loop {
+            let len = rx.recv(&mut buf).unwrap();
+            if is_test_frame(&buf[..len]) {
+                assert_eq!(&buf[..len], &frame[..]);
+                break;
+            }
+        }
+
+        tx.send_async(&frame).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let len = rx.recv_async(&mut buf).await.unwrap();
+                if is_test_frame(&buf[..len]) {
+                    break;
+                }
+            }
+        })
+        .await
+        .expect("frame not received");
+    }
+
+    #[tokio::test]
+    async fn test_frame_stream() {
+        let pair = VethPair::create(&VethConfig::new("vframe0".into(), "vframe1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        let mut tx = pair.dev1().frame_stream().expect("failed to open stream");
+        let rx = pair.dev2().frame_stream().expect("failed to open stream");
+
+        let mut frame = Vec::new();
+        frame.extend_from_slice(pair.dev2().mac_addr());
+        frame.extend_from_slice(pair.dev1().mac_addr());
+        frame.extend_from_slice(&[0x88, 0xb5]);
+        frame.extend_from_slice(b"frame stream test payload, padded to the minimum size");
+        let frame = bytes::Bytes::from(frame);
+
+        tx.send(frame.clone()).await.unwrap();
+        let mut rx = rx.filter(|received| futures::future::ready(received[12..14] == [0x88, 0xb5]));
+        let received = tokio::time::timeout(Duration::from_secs(5), rx.next())
+            .await
+            .expect("frame not received");
+        assert_eq!(received, Some(frame));
+    }
+
+    #[tokio::test]
+    async fn test_capture_to_pcap() {
+        let pair = VethPair::create(&VethConfig::new("vcap0".into(), "vcap1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        let dir = std::env::temp_dir();
+        let pcap_path = dir.join(format!("veth-util-{}.pcap", std::process::id()));
+        let pcapng_path = dir.join(format!("veth-util-{}.pcapng", std::process::id()));
+        let pcap = pair.dev2().capture_to_pcap(&pcap_path).unwrap();
+        let pcapng = pair.dev1().capture_to_pcap(&pcapng_path).unwrap();
+
+        let mut frame = Vec::new();
+        frame.extend_from_slice(pair.dev2().mac_addr());
+        frame.extend_from_slice(pair.dev1().mac_addr());
+        frame.extend_from_slice(&[0x88, 0xb5]);
+        frame.extend_from_slice(b"capture test payload, padded to the minimum frame size");
+        let tx = pair.dev1().packet_socket().unwrap();
+        for _ in 0..3 {
+            tx.send(&frame).unwrap();
+        }
+        // Sent frames are recorded on the sending end too.
+        assert!(pcapng.stop().unwrap() >= 3);
+        drop(pcap);
+
+        let contains_frames = |data: &[u8]| {
+            data.windows(frame.len())
+                .filter(|window| *window == &frame[..])
+                .count()
+                == 3
+        };
+        let data = std::fs::read(&pcap_path).unwrap();
+        assert_eq!(data[..4], 0xa1b2_3c4du32.to_le_bytes());
+        assert!(contains_frames(&data));
+        let data = std::fs::read(&pcapng_path).unwrap();
+        assert_eq!(data[..4], 0x0a0d_0d0au32.to_le_bytes());
+        assert!(contains_frames(&data));
+        std::fs::remove_file(pcap_path).unwrap();
+        std::fs::remove_file(pcapng_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_pcap() {
+        let pair = VethPair::create(&VethConfig::new("vplay0".into(), "vplay1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        let mut frame = Vec::new();
+        frame.extend_from_slice(pair.dev2().mac_addr());
+        frame.extend_from_slice(pair.dev1().mac_addr());
+        frame.extend_from_slice(&[0x88, 0xb5]);
+        frame.extend_from_slice(b"replay test payload, padded to the minimum frame size.");
+
+        let dir = std::env::temp_dir();
+        let mut paths = Vec::new();
+        for name in ["replay.pcap", "replay.pcapng"] {
+            let path = dir.join(format!("veth-util-{}-{}", std::process::id(), name));
+            let file = std::fs::File::create(&path).unwrap();
+            let format = pcap::PcapFormat::from_path(&path);
+            let mut writer = pcap::PcapWriter::new(file, format, "vplay0").unwrap();
+            for i in 0..3 {
+                let timestamp = Duration::from_secs(1_700_000_000) + i * Duration::from_millis(100);
+                writer.write_frame(timestamp, &frame).unwrap();
+            }
+            paths.push(path);
+        }
+
+        let rx = pair.dev2().frame_stream().unwrap();
+        let mut rx = rx.filter(|received| futures::future::ready(received[12..14] == [0x88, 0xb5]));
+        for (path, timing) in [
+            (&paths[0], ReplayTiming::Original),
+            (&paths[1], ReplayTiming::Scaled(2.0)),
+            (&paths[1], ReplayTiming::AsFastAsPossible),
+        ] {
+            let start = std::time::Instant::now();
+            assert_eq!(pair.dev1().replay_pcap(path, timing).await.unwrap(), 3);
+            let elapsed = start.elapsed();
+            match timing {
+                ReplayTiming::Original => assert!(elapsed >= Duration::from_millis(200)),
+                ReplayTiming::Scaled(_) => assert!(elapsed >= Duration::from_millis(100)),
+                _ => assert!(elapsed < Duration::from_millis(100)),
+            }
+            for _ in 0..3 {
+                let received = tokio::time::timeout(Duration::from_secs(5), rx.next())
+                    .await
+                    .expect("frame not received");
+                assert_eq!(received.as_deref(), Some(&frame[..]));
+            }
+        }
+        assert!(matches!(
+            pair.dev1()
+                .replay_pcap(&paths[0], ReplayTiming::FixedRate(0.0))
+                .await,
+            Err(VethError::InvalidArgument(_))
+        ));
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_pcap_corrupt_length() {
+        for format in [pcap::PcapFormat::Pcap, pcap::PcapFormat::PcapNg] {
+            let mut data = Vec::new();
+            pcap::PcapWriter::new(&mut data, format, "vcorrupt0").unwrap();
+            match format {
+                // A record header claiming almost 4 GiB of captured data.
+                pcap::PcapFormat::Pcap => {
+                    data.extend_from_slice(&[0; 8]);
+                    data.extend_from_slice(&0xffff_fff0u32.to_le_bytes());
+                    data.extend_from_slice(&0xffff_fff0u32.to_le_bytes());
+                }
+                // An enhanced packet block of almost 4 GiB.
+                pcap::PcapFormat::PcapNg => {
+                    data.extend_from_slice(&6u32.to_le_bytes());
+                    data.extend_from_slice(&0xffff_fff0u32.to_le_bytes());
+                }
+            }
+            let mut reader = pcap::PcapReader::new(&data[..]).unwrap();
+            let err = reader.next_frame().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_frame_matcher() {
+        // A VLAN 42 tagged IPv4/UDP frame from 10.0.0.1:1234 to 10.0.0.2:53.
+        let mut frame = vec![0xff; 12];
+        frame.extend_from_slice(&[0x81, 0x00, 0x00, 0x2a, 0x08, 0x00]);
+        frame.extend_from_slice(&[0x45, 0, 0, 28, 0, 0, 0x40, 0, 64, 17, 0, 0]);
+        frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        frame.extend_from_slice(&[0x04, 0xd2, 0x00, 0x35, 0, 8, 0, 0]);
+
+        let matching = FrameMatcher::new()
+            .ethertype(0x0800)
+            .vlan(42)
+            .src_ip("10.0.0.1".parse().unwrap())
+            .dst_ip("10.0.0.2".parse().unwrap())
+            .ip_protocol(17)
+            .src_port(1234)
+            .dst_port(53)
+            .matching(|frame| frame.len() == 46);
+        assert!(matching.matches(&frame));
+        assert!(FrameMatcher::new().matches(&frame));
+        assert!(!FrameMatcher::new().vlan(43).matches(&frame));
+        assert!(!FrameMatcher::new().dst_port(1234).matches(&frame));
+        assert!(!FrameMatcher::new().ethertype(0x86dd).matches(&frame));
+        assert!(!FrameMatcher::new().matching(|_| false).matches(&frame));
+        assert!(!FrameMatcher::new().matches(&frame[..10]));
+    }
+
+    #[tokio::test]
+    async fn test_expect_frame() {
+        let pair =
+            VethPair::create(&VethConfig::new("vexpect0".into(), "vexpect1".into()).unwrap())
+                .await
+                .expect("failed to create veth pair");
+        let mut rx = pair.dev2().frame_stream().unwrap();
+        let tx = pair.dev1().packet_socket().unwrap();
+
+        let mut frame = Vec::new();
+        frame.extend_from_slice(pair.dev2().mac_addr());
+        frame.extend_from_slice(pair.dev1().mac_addr());
+        frame.extend_from_slice(&[0x88, 0xb5]);
+        frame.extend_from_slice(&[0; 46]);
+        let matcher = FrameMatcher::new().ethertype(0x88b5);
+
+        rx.expect_no_frame(&matcher, Duration::from_millis(50))
+            .await
+            .unwrap();
+        tx.send(&frame).unwrap();
+        let received = rx
+            .expect_frame(&matcher, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(received, frame);
+        assert!(matches!(
+            rx.expect_frame(&matcher, Duration::from_millis(50)).await,
+            Err(VethError::Timeout(_))
+        ));
+
+        tx.send(&frame).unwrap();
+        assert!(matches!(
+            rx.expect_no_frame(&matcher, Duration::from_secs(5)).await,
+            Err(VethError::UnexpectedFrame { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_frame_builder() {
+        let host = NetNs::new().expect("failed to create netns");
+        let veth_config = VethConfig::new("vbuild0".into(), "vbuild1".into())
+            .unwrap()
+            .dev1_address("10.88.0.1/24".parse().unwrap())
+            .dev1_address("fd00:88::1/64".parse::<LinkAddress>().unwrap().nodad())
+            .dev2_address("10.88.0.2/24".parse().unwrap())
+            .dev2_address("fd00:88::2/64".parse::<LinkAddress>().unwrap().nodad())
+            .dev2_netns(&host);
+        let pair = VethPair::create(&veth_config)
+            .await
+            .expect("failed to create veth pair");
+        pair.install_static_neighbors().await.unwrap();
+        let builder = pair.frame_builder();
+        assert_eq!(builder.src_mac(), pair.dev1().mac_addr());
+        assert_eq!(builder.reversed().src_mac(), pair.dev2().mac_addr());
+
+        let (v4, peer_v4): (std::net::IpAddr, std::net::IpAddr) =
+            ("10.88.0.1".parse().unwrap(), "10.88.0.2".parse().unwrap());
+        let (v6, peer_v6): (std::net::IpAddr, std::net::IpAddr) =
+            ("fd00:88::1".parse().unwrap(), "fd00:88::2".parse().unwrap());
+        let tx = pair.dev1().packet_socket().unwrap();
+        let mut rx = pair.dev1().frame_stream().unwrap();
+        let timeout = Duration::from_secs(5);
+
+        // The peer's stack drops packets with bad checksums, so getting its UDP socket to
+        // receive the datagrams and getting replies to the rest validates the builder.
+        let sockets = host
+            .run(|| {
+                let v4 = std::net::UdpSocket::bind("10.88.0.2:7000")?;
+                let v6 = std::net::UdpSocket::bind("[fd00:88::2]:7000")?;
+                Ok::<_, std::io::Error>((v4, v6))
+            })
+            .unwrap()
+            .unwrap();
+        for (socket, src, dst) in [(&sockets.0, v4, peer_v4), (&sockets.1, v6, peer_v6)] {
+            socket.set_read_timeout(Some(timeout)).unwrap();
+            let frame = builder
+                .udp((src, 7001).into(), (dst, 7000).into(), b"hello")
+                .unwrap();
+            tx.send(&frame).unwrap();
+            let mut buf = [0; 16];
+            let (len, from) = socket.recv_from(&mut buf).unwrap();
+            assert_eq!(&buf[..len], b"hello");
+            assert_eq!(from, (src, 7001).into());
+
+            let frame = builder.icmp_echo_request(src, dst, 1, 1, b"ping").unwrap();
+            tx.send(&frame).unwrap();
+            let reply = FrameMatcher::new()
+                .src_ip(dst)
+                .ip_protocol(if src.is_ipv4() { 1 } else { 58 });
+            rx.expect_frame(&reply, timeout).await.unwrap();
+
+            let frame = builder
+                .tcp(
+                    (src, 7002).into(),
+                    (dst, 7003).into(),
+                    1,
+                    0,
+                    TcpFlags::SYN,
+                    &[],
+                )
+                .unwrap();
+            tx.send(&frame).unwrap();
+            let rst = FrameMatcher::new()
+                .src_ip(dst)
+                .src_port(7003)
+                .dst_port(7002);
+            rx.expect_frame(&rst, timeout).await.unwrap();
+        }
+
+        let frame = builder.arp_request("10.88.0.1".parse().unwrap(), "10.88.0.2".parse().unwrap());
+        tx.send(&frame).unwrap();
+        let arp_reply = FrameMatcher::new()
+            .ethertype(0x0806)
+            .matching(|frame| frame[20..22] == [0, 2]);
+        rx.expect_frame(&arp_reply, timeout).await.unwrap();
+
+        assert!(matches!(
+            builder.udp((v4, 1).into(), (peer_v6, 2).into(), &[]),
+            Err(VethError::InvalidArgument(_))
+        ));
+    }
+
+    /// Loads an XDP program that passes every frame, returning its fd.
+    fn load_xdp_pass() -> std::os::unix::io::OwnedFd {
+        use std::os::unix::io::FromRawFd;
+
+        // r0 = XDP_PASS; exit
+        let insns: [u64; 2] = [0x0000_0002_0000_00b7, 0x0000_0000_0000_0095];
+        let license = b"GPL\0";
+        // The leading members of the `BPF_PROG_LOAD` variant of `union bpf_attr`.
+        let mut attr = [0u8; 128];
+        attr[0..4].copy_from_slice(&6u32.to_ne_bytes()); // BPF_PROG_TYPE_XDP
+        attr[4..8].copy_from_slice(&(insns.len() as u32).to_ne_bytes());
+        attr[8..16].copy_from_slice(&(insns.as_ptr() as u64).to_ne_bytes());
+        attr[16..24].copy_from_slice(&(license.as_ptr() as u64).to_ne_bytes());
+        let fd = unsafe { libc::syscall(libc::SYS_bpf, 5, attr.as_ptr(), attr.len()) };
+        assert!(
+            fd >= 0,
+            "BPF_PROG_LOAD failed: {}",
+            io::Error::last_os_error()
+        );
+        unsafe { std::os::unix::io::OwnedFd::from_raw_fd(fd as i32) }
+    }
+
+    #[tokio::test]
+    async fn test_xdp() {
+        let pair = VethPair::create(&VethConfig::new("vxdp0".into(), "vxdp1".into()).unwrap())
+            .await
+            .expect("failed to create veth pair");
+        let prog = load_xdp_pass();
+        assert_eq!(pair.dev1().xdp_prog_id().await.unwrap(), None);
+
+        for mode in [XdpMode::Auto, XdpMode::Native, XdpMode::Generic] {
+            let attachment = pair
+                .dev1()
+                .attach_xdp(prog.as_raw_fd(), mode)
+                .await
+                .unwrap_or_else(|e| panic!("failed to attach in {:?} mode: {}", mode, e));
+            assert_eq!(
+                pair.dev1().xdp_prog_id().await.unwrap(),
+                Some(attachment.prog_id())
+            );
+            let output = std::process::Command::new("ip")
+                .args(["link", "show", "vxdp0"])
+                .output()
+                .unwrap();
+            let output = String::from_utf8_lossy(&output.stdout);
+            let expected = if mode == XdpMode::Generic {
+                "xdpgeneric"
+            } else {
+                "xdp "
+            };
+            assert!(output.contains(expected), "{}", output);
+
+            // Neither replaces the attached program, nor reads as a name collision.
+            let err = pair
+                .dev1()
+                .attach_xdp(prog.as_raw_fd(), mode)
+                .await
+                .unwrap_err();
+            assert_eq!(err.errno(), Some(nix::errno::Errno::EBUSY as i32));
+            if mode == XdpMode::Native {
+                let err = pair
+                    .dev1()
+                    .attach_xdp(prog.as_raw_fd(), XdpMode::Generic)
+                    .await
+                    .unwrap_err();
+                assert!(matches!(err, VethError::Netlink(_)), "{}", err);
+            }
+            drop(attachment);
+            assert_eq!(pair.dev1().xdp_prog_id().await.unwrap(), None);
+        }
+
+        // Pin the program on a private BPF filesystem and attach it from there.
+        let bpffs = std::env::temp_dir().join(format!("veth-util-bpffs-{}", std::process::id()));
+        std::fs::create_dir_all(&bpffs).unwrap();
+        nix::mount::mount(
+            Some("bpf"),
+            &bpffs,
+            Some("bpf"),
+            nix::mount::MsFlags::empty(),
+            None::<&str>,
+        )
+        .unwrap();
+        let pin =
+            std::ffi::CString::new(bpffs.join("xdp_pass").into_os_string().into_vec()).unwrap();
+        let mut attr = [0u8; 16];
+        attr[0..8].copy_from_slice(&(pin.as_ptr() as u64).to_ne_bytes());
+        attr[8..12].copy_from_slice(&(prog.as_raw_fd() as u32).to_ne_bytes());
+        let res = unsafe { libc::syscall(libc::SYS_bpf, 6, attr.as_ptr(), attr.len()) };
+        assert_eq!(res, 0, "BPF_OBJ_PIN failed: {}", io::Error::last_os_error());
+
+        let attachment = pair
+            .dev2()
+            .attach_xdp_pinned(bpffs.join("xdp_pass"), XdpMode::Native)
+            .await
+            .unwrap();
+        assert_eq!(
+            pair.dev2().xdp_prog_id().await.unwrap(),
+            Some(attachment.prog_id())
+        );
+        attachment.detach().await.unwrap();
+        assert_eq!(pair.dev2().xdp_prog_id().await.unwrap(), None);
+
+        nix::mount::umount2(&bpffs, nix::mount::MntFlags::MNT_DETACH).unwrap();
+        std::fs::remove_dir(&bpffs).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        for (dev1, dev2) in [
+            ("", "veth1"),
+            ("veth0", "0123456789abcdef"),
+            ("veth/0", "veth1"),
+            ("veth0", "veth 1"),
+            ("veth0:1", "veth1"),
+            (".", "veth1"),
+            ("veth0", ".."),
+            ("veth0", "veth0"),
+        ] {
+            assert!(
+                matches!(
+                    VethConfig::new(dev1.into(), dev2.into()),
+                    Err(VethError::InvalidName { .. })
+                ),
+                "{:?}/{:?} accepted",
+                dev1,
+                dev2
+            );
+        }
+        assert!(VethConfig::new("0123456789abcde".into(), "veth1".into()).is_ok());
+        assert!(VethConfig::with_name_prefix("v/").is_err());
+    }
+
+    #[test]
+    fn test_parse_link_address() {
+        let addr: LinkAddress = "192.0.2.1".parse().unwrap();
+        assert_eq!(addr.prefix_len(), 32);
+        assert_eq!(addr.to_string(), "192.0.2.1/32");
+        assert!("192.0.2.1/33".parse::<LinkAddress>().is_err());
+        assert!(matches!(
+            "fd00::1/129".parse::<LinkAddress>(),
+            Err(VethError::InvalidArgument(_))
+        ));
+        assert!("not-an-address/24".parse::<LinkAddress>().is_err());
+    }
 }
@@ -0,0 +1,63 @@
+use std::net::IpAddr;
+
+use netlink_packet_route::neighbour::{NeighbourAddress, NeighbourAttribute, NeighbourMessage};
+use netlink_packet_route::AddressFamily;
+
+use crate::{Result, VethError, VethLink, VethPair};
+
+impl VethLink {
+    /// Installs a permanent neighbor entry mapping `ip` to `mac` on this link, replacing any
+    /// existing entry (equivalent to `ip neigh replace IP lladdr MAC dev IFNAME nud permanent`).
+    pub async fn add_neighbor(&self, ip: IpAddr, mac: [u8; 6]) -> Result<()> {
+        self.handle
+            .neighbours()
+            .add(self.index, ip)
+            .link_local_address(&mac)
+            .replace()
+            .execute()
+            .await
+            .map_err(VethError::from)
+    }
+
+    /// Removes the neighbor entry for `ip` from this link (equivalent to
+    /// `ip neigh del IP dev IFNAME`).
+    pub async fn remove_neighbor(&self, ip: IpAddr) -> Result<()> {
+        let mut message = NeighbourMessage::default();
+        message.header.ifindex = self.index;
+        let destination = match ip {
+            IpAddr::V4(ip) => {
+                message.header.family = AddressFamily::Inet;
+                NeighbourAddress::Inet(ip)
+            }
+            IpAddr::V6(ip) => {
+                message.header.family = AddressFamily::Inet6;
+                NeighbourAddress::Inet6(ip)
+            }
+        };
+        message
+            .attributes
+            .push(NeighbourAttribute::Destination(destination));
+
+        self.handle
+            .neighbours()
+            .del(message)
+            .execute()
+            .await
+            .map_err(VethError::from)
+    }
+}
+
+impl VethPair {
+    /// Installs permanent ARP/NDP entries on each end for every address currently assigned
+    /// to the other end, so no neighbor resolution happens between the two.
+    ///
+    /// Addresses added afterwards are not covered; call this again after changing them.
+    pub async fn install_static_neighbors(&self) -> Result<()> {
+        for (link, peer) in [(&self.dev1, &self.dev2), (&self.dev2, &self.dev1)] {
+            for address in peer.addresses().await? {
+                link.add_neighbor(address.addr(), peer.mac_addr).await?;
+            }
+        }
+        Ok(())
+    }
+}
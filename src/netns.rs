@@ -0,0 +1,360 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::future::Future;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsFd, AsRawFd, OwnedFd};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::channel::oneshot;
+use futures::stream::StreamExt;
+use netlink_packet_core::{NetlinkMessage, NetlinkPayload, NLM_F_REQUEST};
+use netlink_packet_route::nsid::{NsidAttribute, NsidMessage};
+use netlink_packet_route::RouteNetlinkMessage;
+use netlink_sys::{AsyncSocket, SocketAddr};
+use nix::mount::{mount, umount2, MntFlags, MsFlags};
+use nix::sched::{setns, unshare, CloneFlags};
+use rtnetlink::Handle;
+use tokio::task::JoinHandle;
+
+use crate::{Result, VethError};
+
+/// Identifies the network namespace a veth end lives in.
+#[derive(Debug, Clone)]
+pub enum NetnsId {
+    /// A namespace bind-mounted under `/run/netns`, as created by `ip netns add`.
+    Named(String),
+    /// An open file descriptor referring to a namespace, e.g. `/proc/<pid>/ns/net`.
+    ///
+    /// The descriptor is shared by all clones of this id, including those kept by the links
+    /// placed in the namespace, and holds the namespace alive until the last one is dropped.
+    Fd(Arc<OwnedFd>),
+    /// The namespace of the process with the given PID.
+    Pid(u32),
+}
+
+impl NetnsId {
+    /// Opens a fresh handle to the namespace, suitable for `setns` or `IFLA_NET_NS_FD`.
+    pub(crate) fn open(&self) -> Result<File> {
+        let res = match self {
+            NetnsId::Named(name) => File::open(named_netns_path(name)),
+            NetnsId::Fd(fd) => fd.try_clone().map(File::from),
+            NetnsId::Pid(pid) => File::open(format!("/proc/{}/ns/net", pid)),
+        };
+        res.map_err(|e| VethError::netns(self, e))
+    }
+
+    /// A [`NetnsId::Fd`] for the same namespace, so it can still be entered after its name
+    /// is unmounted or its process exits.
+    pub(crate) fn pin(&self) -> Result<NetnsId> {
+        match self {
+            NetnsId::Fd(_) => Ok(self.clone()),
+            _ => Ok(OwnedFd::from(self.open()?).into()),
+        }
+    }
+}
+
+impl fmt::Display for NetnsId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetnsId::Named(name) => write!(f, "{}", name),
+            NetnsId::Fd(fd) => write!(f, "fd {}", fd.as_raw_fd()),
+            NetnsId::Pid(pid) => write!(f, "of pid {}", pid),
+        }
+    }
+}
+
+/// Two ids are equal if they have the same name, or if their descriptors refer to the same
+/// namespace.
+impl PartialEq for NetnsId {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (NetnsId::Named(a), NetnsId::Named(b)) => a == b,
+            (NetnsId::Fd(a), NetnsId::Fd(b)) => Arc::ptr_eq(a, b) || same_inode(a, b),
+            (NetnsId::Pid(a), NetnsId::Pid(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for NetnsId {}
+
+fn same_inode(a: &OwnedFd, b: &OwnedFd) -> bool {
+    let metadata = |fd: &OwnedFd| fd.try_clone().and_then(|fd| File::from(fd).metadata());
+    match (metadata(a), metadata(b)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+impl From<OwnedFd> for NetnsId {
+    fn from(fd: OwnedFd) -> Self {
+        NetnsId::Fd(Arc::new(fd))
+    }
+}
+
+impl From<&NetNs> for NetnsId {
+    fn from(netns: &NetNs) -> Self {
+        match &netns.name {
+            Some(name) => NetnsId::Named(name.clone()),
+            None => NetnsId::Fd(netns.fd.clone()),
+        }
+    }
+}
+
+const NETNS_RUN_DIR: &str = "/run/netns";
+
+fn named_netns_path(name: &str) -> PathBuf {
+    PathBuf::from(NETNS_RUN_DIR).join(name)
+}
+
+/// A network namespace owned by this process, deleted when dropped.
+///
+/// A namespace lives for as long as a process runs inside it or an open file descriptor
+/// refers to it, such as the one held by this value and shared with any [`NetnsId`] taken
+/// from it, or the one a [`VethPair`](crate::VethPair) keeps for each end placed inside.
+/// Links themselves do not keep it alive; they are destroyed along with it. Named namespaces
+/// are additionally bind-mounted under `/run/netns` so that they are visible to `ip netns`,
+/// and that mount is removed on drop.
+#[derive(Debug)]
+pub struct NetNs {
+    fd: Arc<OwnedFd>,
+    name: Option<String>,
+}
+
+impl NetNs {
+    /// Creates an anonymous network namespace.
+    pub fn new() -> Result<Self> {
+        let file = std::thread::spawn(|| -> Result<File> {
+            unshare(CloneFlags::CLONE_NEWNET).map_err(|e| VethError::netns("(anonymous)", e))?;
+            Ok(File::open("/proc/thread-self/ns/net")?)
+        })
+        .join()
+        .expect("netns creation thread panicked")?;
+
+        Ok(Self {
+            fd: Arc::new(file.into()),
+            name: None,
+        })
+    }
+
+    /// Creates a network namespace bind-mounted at `/run/netns/<name>`, like `ip netns add`.
+    pub fn new_named(name: &str) -> Result<Self> {
+        let path = named_netns_path(name);
+        fs::create_dir_all(NETNS_RUN_DIR).map_err(|e| VethError::netns(name, e))?;
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| VethError::netns(name, e))?;
+
+        let mount_path = path.clone();
+        let res = std::thread::spawn(move || -> nix::Result<()> {
+            unshare(CloneFlags::CLONE_NEWNET)?;
+            mount(
+                Some("/proc/thread-self/ns/net"),
+                &mount_path,
+                None::<&str>,
+                MsFlags::MS_BIND,
+                None::<&str>,
+            )
+        })
+        .join()
+        .expect("netns creation thread panicked")
+        .map_err(|e| VethError::netns(name, e));
+        if let Err(e) = res {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+
+        Ok(Self {
+            fd: Arc::new(File::open(&path)?.into()),
+            name: Some(name.into()),
+        })
+    }
+
+    /// The name under `/run/netns`, or `None` for an anonymous namespace.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Runs `f` to completion on a dedicated thread that has entered this namespace.
+    ///
+    /// Sockets and netlink connections opened by `f` stay bound to the namespace after it
+    /// returns.
+    pub fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send,
+        T: Send,
+    {
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    setns(self.fd.as_fd(), CloneFlags::CLONE_NEWNET)
+                        .map_err(|e| VethError::netns(NetnsId::from(self), e))?;
+                    Ok(f())
+                })
+                .join()
+                .expect("netns thread panicked")
+        })
+    }
+
+    /// Spawns the future returned by `f` on a dedicated thread running its own tokio runtime
+    /// inside this namespace, and returns a future resolving to its output.
+    pub fn spawn_in<F, Fut>(&self, f: F) -> Result<impl Future<Output = Result<Fut::Output>>>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future,
+        Fut::Output: Send + 'static,
+    {
+        let id = NetnsId::from(self);
+        let fd = self.fd.clone();
+        let (tx, rx) = oneshot::channel();
+        std::thread::spawn(move || {
+            let res = setns(fd.as_fd(), CloneFlags::CLONE_NEWNET)
+                .map_err(|e| VethError::netns(id, e))
+                .and_then(|_| {
+                    Ok(tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()?)
+                })
+                .map(|rt| rt.block_on(f()));
+            let _ = tx.send(res);
+        });
+
+        Ok(async move { rx.await.expect("netns task panicked") })
+    }
+}
+
+impl Drop for NetNs {
+    fn drop(&mut self) {
+        if let Some(name) = &self.name {
+            let path = named_netns_path(name);
+            if let Err(e) = umount2(&path, MntFlags::MNT_DETACH) {
+                eprintln!("failed to unmount network namespace {}: {}", name, e);
+            }
+            if let Err(e) = fs::remove_file(&path) {
+                eprintln!("failed to remove network namespace {}: {}", name, e);
+            }
+        }
+    }
+}
+
+/// Runs `f` in `netns`, or directly on the caller's thread if `None`.
+///
+/// Sockets stay in the namespace they were created in, so `f` runs on a short-lived thread
+/// that has joined `netns`.
+pub(crate) fn run_in<F, T>(netns: Option<&NetnsId>, f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send,
+    T: Send,
+{
+    let netns = match netns {
+        None => return f(),
+        Some(netns) => netns,
+    };
+    let ns_file = netns.open()?;
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                setns(&ns_file, CloneFlags::CLONE_NEWNET)
+                    .map_err(|e| VethError::netns(netns, e))?;
+                f()
+            })
+            .join()
+            .expect("netns thread panicked")
+    })
+}
+
+/// Multicast notifications received on a connection opened by [`new_subscription_in`].
+pub(crate) type Notifications =
+    UnboundedReceiver<(NetlinkMessage<RouteNetlinkMessage>, SocketAddr)>;
+
+/// Opens an rtnetlink connection bound to `netns`, or to the caller's namespace if `None`.
+///
+/// The socket is opened through [`run_in`], while the connection itself is driven on the
+/// caller's runtime.
+pub(crate) fn new_connection_in(netns: Option<&NetnsId>) -> Result<(Handle, JoinHandle<()>)> {
+    let (handle, _, join_handle) = new_subscription_in(netns, 0)?;
+    Ok((handle, join_handle))
+}
+
+/// Like [`new_connection_in`], but also joins the rtnetlink multicast `groups` (a mask of
+/// `RTMGRP_*` constants) and returns the notifications they deliver.
+pub(crate) fn new_subscription_in(
+    netns: Option<&NetnsId>,
+    groups: u32,
+) -> Result<(Handle, Notifications, JoinHandle<()>)> {
+    let rt = tokio::runtime::Handle::current();
+    let (mut connection, handle, notifications) = run_in(netns, || {
+        let _guard = rt.enter();
+        Ok(rtnetlink::new_connection()?)
+    })?;
+    if groups != 0 {
+        connection
+            .socket_mut()
+            .socket_mut()
+            .bind(&SocketAddr::new(0, groups))?;
+    }
+
+    Ok((handle, notifications, tokio::spawn(connection)))
+}
+
+/// Returns the id that the namespace behind `handle` assigned to the namespace `ns_file`, or
+/// `None` if it has not assigned one.
+async fn get_nsid(handle: &Handle, ns_file: &File) -> Result<Option<i32>> {
+    let mut message = NsidMessage::default();
+    message
+        .attributes
+        .push(NsidAttribute::Fd(ns_file.as_raw_fd() as u32));
+    let mut request = NetlinkMessage::from(RouteNetlinkMessage::GetNsId(message));
+    request.header.flags = NLM_F_REQUEST;
+
+    let mut response = handle.clone().request(request)?;
+    while let Some(message) = response.next().await {
+        match message.payload {
+            NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewNsId(message)) => {
+                // NETNSA_NSID_NOT_ASSIGNED is reported as -1.
+                return Ok(message
+                    .attributes
+                    .iter()
+                    .find_map(|attr| match attr {
+                        NsidAttribute::Id(id) => Some(*id),
+                        _ => None,
+                    })
+                    .filter(|id| *id >= 0));
+            }
+            NetlinkPayload::Error(e) => return Err(rtnetlink::Error::NetlinkError(e).into()),
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Finds the namespace under `/run/netns` known as `nsid` to the namespace behind `handle`.
+///
+/// Anonymous namespaces cannot be found this way, since nothing maps an id back to them.
+pub(crate) async fn find_named_netns(handle: &Handle, nsid: i32) -> Result<Option<NetnsId>> {
+    let entries = match fs::read_dir(NETNS_RUN_DIR) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        // Stale entries whose namespace is gone fail to open; skip them.
+        let ns_file = match File::open(entry.path()) {
+            Ok(ns_file) => ns_file,
+            Err(_) => continue,
+        };
+        if get_nsid(handle, &ns_file).await? == Some(nsid) {
+            return Ok(Some(NetnsId::Named(name)));
+        }
+    }
+    Ok(None)
+}
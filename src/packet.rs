@@ -0,0 +1,305 @@
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+use crate::netns::run_in;
+use crate::{Result, VethError, VethLink};
+
+/// A raw `AF_PACKET` socket bound to one veth end, sending and receiving whole Ethernet frames.
+///
+/// Only frames arriving at the link are received; frames the link transmits, including
+/// those sent through this socket, are not.
+#[derive(Debug)]
+pub struct PacketSocket {
+    // Declared before `fd` so that it is deregistered before the descriptor is closed.
+    reactor: OnceLock<AsyncFd<Fd>>,
+    reactor_init: Mutex<()>,
+    fd: OwnedFd,
+    ifname: String,
+    index: u32,
+}
+
+/// A borrowed descriptor registered with the tokio reactor on first async use.
+#[derive(Debug)]
+pub(crate) struct Fd(RawFd);
+
+impl AsRawFd for Fd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+fn cvt(res: libc::c_int) -> io::Result<libc::c_int> {
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+fn cvt_size(res: libc::ssize_t) -> io::Result<usize> {
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res as usize)
+    }
+}
+
+fn set_option<T>(fd: &OwnedFd, level: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+    cvt(unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            level,
+            name,
+            value as *const T as *const libc::c_void,
+            mem::size_of::<T>() as libc::socklen_t,
+        )
+    })
+    .map(|_| ())
+}
+
+fn open_socket(index: u32, outgoing: bool) -> io::Result<OwnedFd> {
+    // Protocol 0 receives nothing until the socket is bound, so no frames from other
+    // interfaces are queued in between.
+    let fd = cvt(unsafe {
+        libc::socket(
+            libc::AF_PACKET,
+            libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            0,
+        )
+    })?;
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    if outgoing {
+        set_option(
+            &fd,
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPNS,
+            &1 as &libc::c_int,
+        )?;
+    } else {
+        set_option(
+            &fd,
+            libc::SOL_PACKET,
+            libc::PACKET_IGNORE_OUTGOING,
+            &1 as &libc::c_int,
+        )?;
+    }
+
+    let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as u16;
+    addr.sll_protocol = (libc::ETH_P_ALL as u16).to_be();
+    addr.sll_ifindex = index as i32;
+    cvt(unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+        )
+    })?;
+
+    Ok(fd)
+}
+
+impl PacketSocket {
+    /// Enables or disables promiscuous mode on the link for as long as this socket is open.
+    ///
+    /// Packet sockets see every frame arriving at a veth end regardless of its destination
+    /// MAC; this only matters to the rest of the stack and to programs sharing the link.
+    pub fn set_promiscuous(&self, promiscuous: bool) -> Result<()> {
+        let mut mreq: libc::packet_mreq = unsafe { mem::zeroed() };
+        mreq.mr_ifindex = self.index as i32;
+        mreq.mr_type = libc::PACKET_MR_PROMISC as u16;
+        let option = if promiscuous {
+            libc::PACKET_ADD_MEMBERSHIP
+        } else {
+            libc::PACKET_DROP_MEMBERSHIP
+        };
+        Ok(set_option(&self.fd, libc::SOL_PACKET, option, &mreq)?)
+    }
+
+    /// Transmits `frame`, a complete Ethernet frame without FCS, out of the link.
+    pub fn send(&self, frame: &[u8]) -> Result<usize> {
+        loop {
+            match self.try_send(frame) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.poll(libc::POLLOUT)?,
+                res => return Ok(res?),
+            }
+        }
+    }
+
+    /// Receives the next frame arriving at the link into `buf`, blocking until one does.
+    ///
+    /// Returns the length of the frame; frames longer than `buf` are truncated.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match self.try_recv(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.poll(libc::POLLIN)?,
+                res => return Ok(res?),
+            }
+        }
+    }
+
+    /// Like [`PacketSocket::send`], but waits on the tokio reactor instead of blocking.
+    pub async fn send_async(&self, frame: &[u8]) -> Result<usize> {
+        Ok(self
+            .reactor()?
+            .async_io(Interest::WRITABLE, |_| self.try_send(frame))
+            .await?)
+    }
+
+    /// Like [`PacketSocket::recv`], but waits on the tokio reactor instead of blocking.
+    pub async fn recv_async(&self, buf: &mut [u8]) -> Result<usize> {
+        Ok(self
+            .reactor()?
+            .async_io(Interest::READABLE, |_| self.try_recv(buf))
+            .await?)
+    }
+
+    pub fn ifname(&self) -> &str {
+        &self.ifname
+    }
+
+    pub(crate) fn try_send(&self, frame: &[u8]) -> io::Result<usize> {
+        cvt_size(unsafe {
+            libc::send(
+                self.fd.as_raw_fd(),
+                frame.as_ptr() as *const libc::c_void,
+                frame.len(),
+                0,
+            )
+        })
+    }
+
+    pub(crate) fn try_recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        // MSG_TRUNC reports the full length of frames that did not fit.
+        cvt_size(unsafe {
+            libc::recv(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                libc::MSG_TRUNC,
+            )
+        })
+        .map(|len| len.min(buf.len()))
+    }
+
+    /// Like [`PacketSocket::try_recv`], also returning the kernel's receive timestamp as the
+    /// time since the Unix epoch. Only sockets opened for capture record timestamps.
+    pub(crate) fn try_recv_timestamped(&self, buf: &mut [u8]) -> io::Result<(usize, Duration)> {
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let mut control = [0u64; 8];
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        let len =
+            cvt_size(unsafe { libc::recvmsg(self.fd.as_raw_fd(), &mut msg, libc::MSG_TRUNC) })?;
+
+        let mut timestamp = None;
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let header = unsafe { &*cmsg };
+            if header.cmsg_level == libc::SOL_SOCKET && header.cmsg_type == libc::SCM_TIMESTAMPNS {
+                let ts =
+                    unsafe { (libc::CMSG_DATA(cmsg) as *const libc::timespec).read_unaligned() };
+                timestamp = Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32));
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+        // Fall back to the current time should the kernel not have stamped the frame.
+        let timestamp = timestamp.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+        });
+
+        Ok((len.min(buf.len()), timestamp))
+    }
+
+    /// Waits until the socket is readable or `wake` (an eventfd) is signalled.
+    pub(crate) fn wait_readable(&self, wake: &OwnedFd) -> io::Result<()> {
+        let mut pollfds = [
+            libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: wake.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        match cvt(unsafe { libc::poll(pollfds.as_mut_ptr(), 2, -1) }) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(()),
+            res => res.map(|_| ()),
+        }
+    }
+
+    pub(crate) fn reactor(&self) -> io::Result<&AsyncFd<Fd>> {
+        if let Some(reactor) = self.reactor.get() {
+            return Ok(reactor);
+        }
+        // A second registration that lost the race would deregister the descriptor again
+        // when dropped, so registrations must not overlap.
+        let _guard = self.reactor_init.lock().unwrap();
+        if let Some(reactor) = self.reactor.get() {
+            return Ok(reactor);
+        }
+        // SAFETY: the registration is dropped before `fd` is closed, see the field order.
+        let reactor =
+            unsafe { AsyncFd::register(Fd(self.fd.as_raw_fd())) }.map_err(|e| e.into_parts().1)?;
+        Ok(self.reactor.get_or_init(|| reactor))
+    }
+
+    fn poll(&self, events: libc::c_short) -> io::Result<()> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events,
+            revents: 0,
+        };
+        match cvt(unsafe { libc::poll(&mut pollfd, 1, -1) }) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(()),
+            res => res.map(|_| ()),
+        }
+    }
+}
+
+impl VethLink {
+    /// Opens a raw packet socket on this link, in the namespace the link lives in.
+    pub fn packet_socket(&self) -> Result<PacketSocket> {
+        self.open_packet_socket(false)
+    }
+
+    /// Opens a packet socket that, if `outgoing` is set, also receives the frames the link
+    /// transmits, with receive timestamps.
+    pub(crate) fn open_packet_socket(&self, outgoing: bool) -> Result<PacketSocket> {
+        let index = self.index;
+        let fd = run_in(self.pinned_netns.as_ref(), || {
+            open_socket(index, outgoing).map_err(|e| match e.raw_os_error() {
+                Some(libc::EPERM) | Some(libc::EACCES) => VethError::PermissionDenied,
+                Some(libc::ENODEV) => VethError::LinkVanished(self.ifname.clone()),
+                _ => e.into(),
+            })
+        })?;
+
+        Ok(PacketSocket {
+            reactor: OnceLock::new(),
+            reactor_init: Mutex::new(()),
+            fd,
+            ifname: self.ifname.clone(),
+            index,
+        })
+    }
+}
@@ -0,0 +1,390 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// `LINKTYPE_ETHERNET`, the only link type a veth end produces.
+const LINKTYPE_ETHERNET: u16 = 1;
+/// Frames are never truncated on capture, so the snap length is the largest frame possible.
+const SNAPLEN: u32 = 262_144;
+/// Largest pcapng block read, as in Wireshark, so a corrupt length cannot exhaust memory.
+const PCAPNG_MAX_BLOCK_LEN: usize = 16 * 1024 * 1024;
+
+/// Magic number of classic pcap files with nanosecond timestamps.
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+/// Magic number of classic pcap files with microsecond timestamps.
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_OPT_END: u16 = 0;
+const PCAPNG_OPT_IF_NAME: u16 = 2;
+const PCAPNG_OPT_IF_TSRESOL: u16 = 9;
+
+/// The on-disk format of a capture file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PcapFormat {
+    /// Classic libpcap format, written with nanosecond timestamps.
+    Pcap,
+    /// pcapng, written with nanosecond timestamps.
+    PcapNg,
+}
+
+impl PcapFormat {
+    /// Picks the format from the file extension: `.pcapng` selects pcapng, anything else
+    /// classic pcap.
+    pub(crate) fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext == "pcapng" => PcapFormat::PcapNg,
+            _ => PcapFormat::Pcap,
+        }
+    }
+}
+
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+/// Writes Ethernet frames to a pcap or pcapng stream.
+pub(crate) struct PcapWriter<W: Write> {
+    writer: W,
+    format: PcapFormat,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the file header, describing a single interface named `ifname`.
+    pub(crate) fn new(mut writer: W, format: PcapFormat, ifname: &str) -> io::Result<Self> {
+        match format {
+            PcapFormat::Pcap => {
+                writer.write_all(&PCAP_MAGIC_NANOS.to_le_bytes())?;
+                writer.write_all(&2u16.to_le_bytes())?;
+                writer.write_all(&4u16.to_le_bytes())?;
+                // Timezone offset and timestamp accuracy, both always zero.
+                writer.write_all(&[0; 8])?;
+                writer.write_all(&SNAPLEN.to_le_bytes())?;
+                writer.write_all(&u32::from(LINKTYPE_ETHERNET).to_le_bytes())?;
+            }
+            PcapFormat::PcapNg => {
+                let mut body = Vec::new();
+                body.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+                body.extend_from_slice(&1u16.to_le_bytes());
+                body.extend_from_slice(&0u16.to_le_bytes());
+                // Unknown section length.
+                body.extend_from_slice(&(-1i64).to_le_bytes());
+                write_block(&mut writer, PCAPNG_SECTION_HEADER, &body)?;
+
+                let mut body = Vec::new();
+                body.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+                body.extend_from_slice(&0u16.to_le_bytes());
+                body.extend_from_slice(&SNAPLEN.to_le_bytes());
+                push_option(&mut body, PCAPNG_OPT_IF_NAME, ifname.as_bytes());
+                push_option(&mut body, PCAPNG_OPT_IF_TSRESOL, &[9]);
+                push_option(&mut body, PCAPNG_OPT_END, &[]);
+                write_block(&mut writer, PCAPNG_INTERFACE_DESCRIPTION, &body)?;
+            }
+        }
+        Ok(Self { writer, format })
+    }
+
+    /// Appends `frame`, captured at `timestamp` since the Unix epoch.
+    pub(crate) fn write_frame(&mut self, timestamp: Duration, frame: &[u8]) -> io::Result<()> {
+        let len = frame.len() as u32;
+        match self.format {
+            PcapFormat::Pcap => {
+                self.writer
+                    .write_all(&(timestamp.as_secs() as u32).to_le_bytes())?;
+                self.writer
+                    .write_all(&timestamp.subsec_nanos().to_le_bytes())?;
+                self.writer.write_all(&len.to_le_bytes())?;
+                self.writer.write_all(&len.to_le_bytes())?;
+                self.writer.write_all(frame)
+            }
+            PcapFormat::PcapNg => {
+                let nanos = timestamp.as_nanos() as u64;
+                let mut body = Vec::with_capacity(20 + frame.len() + 3);
+                body.extend_from_slice(&0u32.to_le_bytes());
+                body.extend_from_slice(&((nanos >> 32) as u32).to_le_bytes());
+                body.extend_from_slice(&(nanos as u32).to_le_bytes());
+                body.extend_from_slice(&len.to_le_bytes());
+                body.extend_from_slice(&len.to_le_bytes());
+                body.extend_from_slice(frame);
+                body.resize(body.len() + padding(frame.len()), 0);
+                write_block(&mut self.writer, PCAPNG_ENHANCED_PACKET, &body)
+            }
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len() + padding(value.len()), 0);
+}
+
+fn write_block(writer: &mut impl Write, kind: u32, body: &[u8]) -> io::Result<()> {
+    // Block type, both length fields and the body.
+    let total_len = (12 + body.len()) as u32;
+    writer.write_all(&kind.to_le_bytes())?;
+    writer.write_all(&total_len.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&total_len.to_le_bytes())
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Reads Ethernet frames from a pcap or pcapng stream, detecting the format and byte order
+/// from the file header.
+pub(crate) struct PcapReader<R: Read> {
+    reader: R,
+    big_endian: bool,
+    kind: ReaderKind,
+    /// The timestamp of the last frame, reused for pcapng simple packet blocks.
+    last_timestamp: Duration,
+}
+
+enum ReaderKind {
+    /// Classic pcap, with the number of timestamp units per second.
+    Pcap { units_per_sec: u64 },
+    /// pcapng, with the timestamp units per second of each interface in the current section.
+    PcapNg { interfaces: Vec<u64> },
+}
+
+impl<R: Read> PcapReader<R> {
+    pub(crate) fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if u32::from_le_bytes(magic) == PCAPNG_SECTION_HEADER {
+            let mut pcap = Self {
+                reader,
+                big_endian: false,
+                kind: ReaderKind::PcapNg {
+                    interfaces: Vec::new(),
+                },
+                last_timestamp: Duration::ZERO,
+            };
+            pcap.read_section_header()?;
+            return Ok(pcap);
+        }
+
+        let (big_endian, units_per_sec) = match magic {
+            m if u32::from_le_bytes(m) == PCAP_MAGIC_NANOS => (false, 1_000_000_000),
+            m if u32::from_be_bytes(m) == PCAP_MAGIC_NANOS => (true, 1_000_000_000),
+            m if u32::from_le_bytes(m) == PCAP_MAGIC_MICROS => (false, 1_000_000),
+            m if u32::from_be_bytes(m) == PCAP_MAGIC_MICROS => (true, 1_000_000),
+            _ => return Err(invalid_data("not a pcap or pcapng file")),
+        };
+        let mut pcap = Self {
+            reader,
+            big_endian,
+            kind: ReaderKind::Pcap { units_per_sec },
+            last_timestamp: Duration::ZERO,
+        };
+        let mut header = [0; 20];
+        pcap.reader.read_exact(&mut header)?;
+        // The upper bits of the link type field carry FCS information.
+        check_link_type(pcap.u32_at(&header, 16) as u16)?;
+        Ok(pcap)
+    }
+
+    /// Returns the next frame with its timestamp since the Unix epoch, or `None` at the end
+    /// of the file.
+    pub(crate) fn next_frame(&mut self) -> io::Result<Option<(Duration, Vec<u8>)>> {
+        match self.kind {
+            ReaderKind::Pcap { units_per_sec } => {
+                let mut header = [0; 16];
+                if !self.read_or_eof(&mut header)? {
+                    return Ok(None);
+                }
+                let secs = self.u32_at(&header, 0) as u64;
+                let frac = self.u32_at(&header, 4) as u64;
+                let captured = self.u32_at(&header, 8);
+                if captured > SNAPLEN {
+                    return Err(invalid_data("pcap record longer than the snap length"));
+                }
+                let mut frame = vec![0; captured as usize];
+                self.reader.read_exact(&mut frame)?;
+                let timestamp = Duration::from_secs(secs) + ticks_to_duration(frac, units_per_sec);
+                Ok(Some((timestamp, frame)))
+            }
+            ReaderKind::PcapNg { .. } => self.next_pcapng_frame(),
+        }
+    }
+
+    fn next_pcapng_frame(&mut self) -> io::Result<Option<(Duration, Vec<u8>)>> {
+        loop {
+            let mut kind = [0; 4];
+            if !self.read_or_eof(&mut kind)? {
+                return Ok(None);
+            }
+            if u32::from_le_bytes(kind) == PCAPNG_SECTION_HEADER {
+                self.read_section_header()?;
+                continue;
+            }
+            let kind = self.u32_at(&kind, 0);
+            let mut len = [0; 4];
+            self.reader.read_exact(&mut len)?;
+            let len = self.u32_at(&len, 0) as usize;
+            if len < 12 || !len.is_multiple_of(4) || len > PCAPNG_MAX_BLOCK_LEN {
+                return Err(invalid_data("invalid pcapng block length"));
+            }
+            let mut body = vec![0; len - 8];
+            self.reader.read_exact(&mut body)?;
+            body.truncate(len - 12);
+
+            match kind {
+                PCAPNG_INTERFACE_DESCRIPTION => self.read_interface(&body)?,
+                PCAPNG_ENHANCED_PACKET => {
+                    if body.len() < 20 {
+                        return Err(invalid_data("truncated pcapng packet block"));
+                    }
+                    let interface = self.u32_at(&body, 0) as usize;
+                    let units_per_sec = match &self.kind {
+                        ReaderKind::PcapNg { interfaces } => interfaces.get(interface).copied(),
+                        ReaderKind::Pcap { .. } => None,
+                    }
+                    .ok_or_else(|| invalid_data("packet on undeclared pcapng interface"))?;
+                    let ticks = (self.u32_at(&body, 4) as u64) << 32 | self.u32_at(&body, 8) as u64;
+                    let captured = self.u32_at(&body, 12) as usize;
+                    let frame = body
+                        .get(20..20 + captured)
+                        .ok_or_else(|| invalid_data("truncated pcapng packet block"))?;
+                    self.last_timestamp = ticks_to_duration(ticks, units_per_sec);
+                    return Ok(Some((self.last_timestamp, frame.to_vec())));
+                }
+                PCAPNG_SIMPLE_PACKET => {
+                    if body.len() < 4 {
+                        return Err(invalid_data("truncated pcapng packet block"));
+                    }
+                    let original = self.u32_at(&body, 0) as usize;
+                    let frame = &body[4..];
+                    let frame = &frame[..original.min(frame.len())];
+                    return Ok(Some((self.last_timestamp, frame.to_vec())));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads the rest of a section header block whose type has already been read.
+    fn read_section_header(&mut self) -> io::Result<()> {
+        let mut header = [0; 8];
+        self.reader.read_exact(&mut header)?;
+        let magic = [header[4], header[5], header[6], header[7]];
+        self.big_endian = match magic {
+            m if u32::from_le_bytes(m) == PCAPNG_BYTE_ORDER_MAGIC => false,
+            m if u32::from_be_bytes(m) == PCAPNG_BYTE_ORDER_MAGIC => true,
+            _ => return Err(invalid_data("invalid pcapng byte order magic")),
+        };
+        let len = self.u32_at(&header, 0) as usize;
+        if len < 28 || !len.is_multiple_of(4) {
+            return Err(invalid_data("invalid pcapng block length"));
+        }
+        io::copy(
+            &mut (&mut self.reader).take(len as u64 - 12),
+            &mut io::sink(),
+        )?;
+        self.kind = ReaderKind::PcapNg {
+            interfaces: Vec::new(),
+        };
+        Ok(())
+    }
+
+    fn read_interface(&mut self, body: &[u8]) -> io::Result<()> {
+        if body.len() < 8 {
+            return Err(invalid_data("truncated pcapng interface block"));
+        }
+        check_link_type(self.u16_at(body, 0))?;
+
+        let mut units_per_sec = 1_000_000;
+        let mut options = &body[8..];
+        while options.len() >= 4 {
+            let code = self.u16_at(options, 0);
+            let len = self.u16_at(options, 2) as usize;
+            let value = options
+                .get(4..4 + len)
+                .ok_or_else(|| invalid_data("truncated pcapng option"))?;
+            match code {
+                PCAPNG_OPT_END => break,
+                PCAPNG_OPT_IF_TSRESOL if len == 1 => {
+                    let exponent = u32::from(value[0] & 0x7f);
+                    units_per_sec = if value[0] & 0x80 == 0 {
+                        10u64.checked_pow(exponent)
+                    } else {
+                        2u64.checked_pow(exponent)
+                    }
+                    .ok_or_else(|| invalid_data("unsupported pcapng timestamp resolution"))?;
+                }
+                _ => {}
+            }
+            options = &options[(4 + len + padding(len)).min(options.len())..];
+        }
+
+        if let ReaderKind::PcapNg { interfaces } = &mut self.kind {
+            interfaces.push(units_per_sec);
+        }
+        Ok(())
+    }
+
+    /// Fills `buf`, returning `false` if the stream ended cleanly before its first byte.
+    fn read_or_eof(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.reader.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    fn u16_at(&self, buf: &[u8], offset: usize) -> u16 {
+        let bytes = [buf[offset], buf[offset + 1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32_at(&self, buf: &[u8], offset: usize) -> u32 {
+        let bytes = [
+            buf[offset],
+            buf[offset + 1],
+            buf[offset + 2],
+            buf[offset + 3],
+        ];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+fn check_link_type(link_type: u16) -> io::Result<()> {
+    if link_type != LINKTYPE_ETHERNET {
+        return Err(invalid_data(format!(
+            "unsupported link type {}, only Ethernet captures can be replayed",
+            link_type
+        )));
+    }
+    Ok(())
+}
+
+fn ticks_to_duration(ticks: u64, units_per_sec: u64) -> Duration {
+    let nanos = u128::from(ticks % units_per_sec) * 1_000_000_000 / u128::from(units_per_sec);
+    Duration::new(ticks / units_per_sec, nanos as u32)
+}
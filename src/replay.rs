@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::pcap::PcapReader;
+use crate::{Result, VethError, VethLink};
+
+/// How quickly [`VethLink::replay_pcap`] transmits the frames of a capture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayTiming {
+    /// Sends each frame as soon as the previous one has been sent.
+    AsFastAsPossible,
+    /// Keeps the gaps between frames recorded in the capture.
+    Original,
+    /// Divides the recorded gaps by the given factor, e.g. `2.0` replays twice as fast.
+    Scaled(f64),
+    /// Sends the given number of frames per second, ignoring the recorded timestamps.
+    FixedRate(f64),
+}
+
+impl ReplayTiming {
+    /// The offset from the start of the replay at which the `index`-th frame, recorded
+    /// `elapsed` after the first one, is due.
+    fn offset(&self, index: u32, elapsed: Duration) -> Option<Duration> {
+        match *self {
+            ReplayTiming::AsFastAsPossible => None,
+            ReplayTiming::Original => Some(elapsed),
+            ReplayTiming::Scaled(factor) => Some(elapsed.div_f64(factor)),
+            ReplayTiming::FixedRate(rate) => Some(Duration::from_secs_f64(f64::from(index) / rate)),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match *self {
+            ReplayTiming::Scaled(value) | ReplayTiming::FixedRate(value)
+                if !(value.is_finite() && value > 0.0) =>
+            {
+                Err(VethError::InvalidArgument(format!(
+                    "replay speed must be positive, got {}",
+                    value
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl VethLink {
+    /// Transmits the frames of the pcap or pcapng file at `path` out of this link, paced
+    /// according to `timing`, and returns the number of frames sent.
+    ///
+    /// Only Ethernet captures are supported. Frames recorded with a timestamp earlier than
+    /// their predecessor's are sent immediately.
+    pub async fn replay_pcap(&self, path: impl AsRef<Path>, timing: ReplayTiming) -> Result<u32> {
+        timing.validate()?;
+        let mut reader = PcapReader::new(BufReader::new(File::open(path)?))?;
+        let socket = self.packet_socket()?;
+
+        let start = Instant::now();
+        let mut first_timestamp = None;
+        let mut count = 0;
+        while let Some((timestamp, frame)) = reader.next_frame()? {
+            let first_timestamp = *first_timestamp.get_or_insert(timestamp);
+            let elapsed = timestamp.saturating_sub(first_timestamp);
+            if let Some(offset) = timing.offset(count, elapsed) {
+                tokio::time::sleep_until(start + offset).await;
+            }
+            socket.send_async(&frame).await?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
@@ -0,0 +1,162 @@
+use std::net::IpAddr;
+
+use netlink_packet_route::route::{
+    RouteAddress, RouteAttribute, RouteHeader, RouteMessage, RouteProtocol, RouteScope, RouteType,
+};
+use netlink_packet_route::AddressFamily;
+
+use crate::{Result, VethError, VethLink};
+
+/// A route out of a veth end, either a default route or a prefix route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LinkRoute {
+    destination: IpAddr,
+    prefix_len: u8,
+    gateway: Option<IpAddr>,
+    metric: Option<u32>,
+    table: Option<u32>,
+}
+
+impl LinkRoute {
+    /// A default route through `gateway` (equivalent to `ip route add default via GATEWAY`).
+    pub fn default_via(gateway: IpAddr) -> Self {
+        let destination = match gateway {
+            IpAddr::V4(_) => IpAddr::from([0u8; 4]),
+            IpAddr::V6(_) => IpAddr::from([0u8; 16]),
+        };
+        Self {
+            destination,
+            prefix_len: 0,
+            gateway: Some(gateway),
+            metric: None,
+            table: None,
+        }
+    }
+
+    /// A route to `destination/prefix_len` directly out of the link (equivalent to
+    /// `ip route add DESTINATION/PREFIX_LEN dev IFNAME`).
+    pub fn prefix(destination: IpAddr, prefix_len: u8) -> Result<Self> {
+        let max_len = if destination.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_len {
+            return Err(VethError::InvalidArgument(format!(
+                "invalid prefix length {} for {}",
+                prefix_len, destination
+            )));
+        }
+        Ok(Self {
+            destination,
+            prefix_len,
+            gateway: None,
+            metric: None,
+            table: None,
+        })
+    }
+
+    /// Routes through `gateway` rather than directly out of the link.
+    pub fn via(mut self, gateway: IpAddr) -> Result<Self> {
+        if gateway.is_ipv4() != self.destination.is_ipv4() {
+            return Err(VethError::InvalidArgument(format!(
+                "gateway {} does not match route to {}",
+                gateway, self.destination
+            )));
+        }
+        self.gateway = Some(gateway);
+        Ok(self)
+    }
+
+    /// Sets the route metric (equivalent to `ip route add ... metric METRIC`).
+    pub fn metric(mut self, metric: u32) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    /// Installs the route in `table` instead of the main table (equivalent to
+    /// `ip route add ... table TABLE`).
+    pub fn table(mut self, table: u32) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    pub fn destination(&self) -> IpAddr {
+        self.destination
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn gateway(&self) -> Option<IpAddr> {
+        self.gateway
+    }
+
+    fn message(&self, index: u32) -> RouteMessage {
+        let mut message = RouteMessage::default();
+        message.header.address_family = match self.destination {
+            IpAddr::V4(_) => AddressFamily::Inet,
+            IpAddr::V6(_) => AddressFamily::Inet6,
+        };
+        message.header.destination_prefix_length = self.prefix_len;
+        message.header.protocol = RouteProtocol::Static;
+        message.header.kind = RouteType::Unicast;
+        // Routes without a gateway are on-link, as `ip route` would install them.
+        message.header.scope = match self.gateway {
+            Some(_) => RouteScope::Universe,
+            None => RouteScope::Link,
+        };
+
+        match self.table {
+            Some(table) if table > 255 => {
+                message.header.table = RouteHeader::RT_TABLE_UNSPEC;
+                message.attributes.push(RouteAttribute::Table(table));
+            }
+            Some(table) => message.header.table = table as u8,
+            None => message.header.table = RouteHeader::RT_TABLE_MAIN,
+        }
+
+        if self.prefix_len > 0 {
+            message
+                .attributes
+                .push(RouteAttribute::Destination(route_address(self.destination)));
+        }
+        if let Some(gateway) = self.gateway {
+            message
+                .attributes
+                .push(RouteAttribute::Gateway(route_address(gateway)));
+        }
+        if let Some(metric) = self.metric {
+            message.attributes.push(RouteAttribute::Priority(metric));
+        }
+        message.attributes.push(RouteAttribute::Oif(index));
+
+        message
+    }
+}
+
+fn route_address(addr: IpAddr) -> RouteAddress {
+    match addr {
+        IpAddr::V4(addr) => RouteAddress::Inet(addr),
+        IpAddr::V6(addr) => RouteAddress::Inet6(addr),
+    }
+}
+
+impl VethLink {
+    /// Installs `route` out of this link, in the namespace the link lives in.
+    pub async fn add_route(&self, route: &LinkRoute) -> Result<()> {
+        let mut request = self.handle.route().add();
+        *request.message_mut() = route.message(self.index);
+        request.execute().await.map_err(VethError::from)
+    }
+
+    /// Removes a route previously installed with [`VethLink::add_route`].
+    pub async fn remove_route(&self, route: &LinkRoute) -> Result<()> {
+        let mut message = route.message(self.index);
+        // Let the kernel match the route regardless of the scope it was installed with.
+        message.header.scope = RouteScope::NoWhere;
+        self.handle
+            .route()
+            .del(message)
+            .execute()
+            .await
+            .map_err(VethError::from)
+    }
+}
@@ -0,0 +1,92 @@
+use std::io;
+use std::time::Duration;
+
+use futures::stream::StreamExt;
+use netlink_packet_core::NetlinkPayload;
+use netlink_packet_route::link::{LinkAttribute, LinkMessage, State};
+use netlink_packet_route::RouteNetlinkMessage;
+use rtnetlink::constants::RTMGRP_LINK;
+
+use crate::netns::new_subscription_in;
+use crate::{get_link, set_link_down, set_link_up, Result, VethError, VethLink, VethPair};
+
+fn is_oper_up(link: &LinkMessage) -> bool {
+    link.attributes
+        .iter()
+        .any(|attr| matches!(attr, LinkAttribute::OperState(State::Up)))
+}
+
+impl VethLink {
+    /// Sets the link administratively up (equivalent to `ip link set IFNAME up`).
+    pub async fn set_up(&self) -> Result<()> {
+        set_link_up(&self.handle, &self.ifname, self.index).await
+    }
+
+    /// Sets the link administratively down (equivalent to `ip link set IFNAME down`). The peer
+    /// stays up but loses carrier.
+    pub async fn set_down(&self) -> Result<()> {
+        set_link_down(&self.handle, &self.ifname, self.index).await
+    }
+
+    /// Waits until the kernel reports this link as operationally up, i.e. both it and its peer
+    /// are administratively up and the link has carrier.
+    ///
+    /// Fails with [`VethError::Timeout`] if that does not happen within `timeout`.
+    pub async fn wait_oper_up(&self, timeout: Duration) -> Result<()> {
+        let (handle, mut notifications, join_handle) =
+            new_subscription_in(self.pinned_netns.as_ref(), RTMGRP_LINK)?;
+
+        // Subscribe before looking at the current state so that a transition in between is
+        // not missed.
+        let res = tokio::time::timeout(timeout, async {
+            if is_oper_up(&get_link(&handle, &self.ifname, self.index).await?) {
+                return Ok(());
+            }
+            while let Some((message, _)) = notifications.next().await {
+                match message.payload {
+                    NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewLink(link))
+                        if link.header.index == self.index && is_oper_up(&link) =>
+                    {
+                        return Ok(());
+                    }
+                    NetlinkPayload::InnerMessage(RouteNetlinkMessage::DelLink(link))
+                        if link.header.index == self.index =>
+                    {
+                        return Err(VethError::LinkVanished(self.ifname.clone()));
+                    }
+                    _ => {}
+                }
+            }
+            Err(io::Error::other("netlink connection closed").into())
+        })
+        .await;
+        join_handle.abort();
+
+        res.unwrap_or_else(|_| Err(VethError::Timeout(format!("{} to come up", self.ifname))))
+    }
+}
+
+impl VethPair {
+    /// Simulates plugging or pulling the cable at dev1 while it stays administratively up.
+    ///
+    /// veth devices do not let their carrier be set directly (`ip link set ... carrier off`
+    /// fails with `EOPNOTSUPP`); a veth end has carrier exactly when its peer is up, so this
+    /// sets dev2 up or down instead.
+    pub async fn set_dev1_carrier(&self, carrier: bool) -> Result<()> {
+        if carrier {
+            self.dev2.set_up().await
+        } else {
+            self.dev2.set_down().await
+        }
+    }
+
+    /// Simulates plugging or pulling the cable at dev2 by setting dev1 up or down. See
+    /// [`VethPair::set_dev1_carrier`].
+    pub async fn set_dev2_carrier(&self, carrier: bool) -> Result<()> {
+        if carrier {
+            self.dev1.set_up().await
+        } else {
+            self.dev1.set_down().await
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::io;
+
+use netlink_packet_route::link::{LinkAttribute, Stats64};
+
+use crate::{get_link, Result, VethLink};
+
+/// A snapshot of a link's 64-bit counters, as reported by `IFLA_STATS64`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+    pub multicast: u64,
+}
+
+impl LinkStats {
+    /// The counts accumulated between the `earlier` snapshot and this one.
+    pub fn since(&self, earlier: &LinkStats) -> LinkStats {
+        // The kernel's counters only wrap, they never go backwards.
+        LinkStats {
+            rx_packets: self.rx_packets.wrapping_sub(earlier.rx_packets),
+            tx_packets: self.tx_packets.wrapping_sub(earlier.tx_packets),
+            rx_bytes: self.rx_bytes.wrapping_sub(earlier.rx_bytes),
+            tx_bytes: self.tx_bytes.wrapping_sub(earlier.tx_bytes),
+            rx_errors: self.rx_errors.wrapping_sub(earlier.rx_errors),
+            tx_errors: self.tx_errors.wrapping_sub(earlier.tx_errors),
+            rx_dropped: self.rx_dropped.wrapping_sub(earlier.rx_dropped),
+            tx_dropped: self.tx_dropped.wrapping_sub(earlier.tx_dropped),
+            multicast: self.multicast.wrapping_sub(earlier.multicast),
+        }
+    }
+}
+
+impl From<&Stats64> for LinkStats {
+    fn from(stats: &Stats64) -> Self {
+        LinkStats {
+            rx_packets: stats.rx_packets,
+            tx_packets: stats.tx_packets,
+            rx_bytes: stats.rx_bytes,
+            tx_bytes: stats.tx_bytes,
+            rx_errors: stats.rx_errors,
+            tx_errors: stats.tx_errors,
+            rx_dropped: stats.rx_dropped,
+            tx_dropped: stats.tx_dropped,
+            multicast: stats.multicast,
+        }
+    }
+}
+
+impl VethLink {
+    /// Fetches the link's current counters, in the namespace the link lives in.
+    ///
+    /// Fails if the kernel does not report `IFLA_STATS64`, rather than returning zeros.
+    pub async fn stats(&self) -> Result<LinkStats> {
+        let link = get_link(&self.handle, &self.ifname, self.index).await?;
+        link.attributes
+            .iter()
+            .find_map(|attr| match attr {
+                LinkAttribute::Stats64(stats) => Some(LinkStats::from(stats)),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "no statistics for interface").into()
+            })
+    }
+}
@@ -0,0 +1,253 @@
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::Path;
+
+use netlink_packet_route::link::{LinkAttribute, LinkXdp};
+use nix::errno::Errno;
+use rtnetlink::Handle;
+
+use crate::error::netlink_errno;
+use crate::netns::new_connection_in;
+use crate::{get_link, NetnsId, Result, VethError, VethLink};
+
+const XDP_FLAGS_UPDATE_IF_NOEXIST: u32 = 1 << 0;
+const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
+const XDP_FLAGS_DRV_MODE: u32 = 1 << 2;
+
+const BPF_OBJ_GET: libc::c_long = 7;
+
+/// How an XDP program is attached to a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XdpMode {
+    /// Lets the kernel pick: native if the driver supports it, which veth does, otherwise
+    /// generic. Hardware offload is never requested.
+    Auto,
+    /// Generic (SKB) mode, run by the stack after the frame has been turned into an skb
+    /// (equivalent to `ip link set IFNAME xdpgeneric ...`).
+    Generic,
+    /// Native (driver) mode (equivalent to `ip link set IFNAME xdpdrv ...`).
+    Native,
+}
+
+impl XdpMode {
+    fn flags(&self) -> u32 {
+        match self {
+            XdpMode::Auto => 0,
+            XdpMode::Generic => XDP_FLAGS_SKB_MODE,
+            XdpMode::Native => XDP_FLAGS_DRV_MODE,
+        }
+    }
+}
+
+/// An XDP program attached to a veth end, detached again when dropped.
+#[derive(Debug)]
+pub struct XdpAttachment {
+    ifname: String,
+    index: u32,
+    netns: Option<NetnsId>,
+    handle: Handle,
+    mode: XdpMode,
+    prog_id: u32,
+    detach_on_drop: bool,
+}
+
+impl XdpAttachment {
+    /// The kernel's id of the attached program.
+    pub fn prog_id(&self) -> u32 {
+        self.prog_id
+    }
+
+    pub fn mode(&self) -> XdpMode {
+        self.mode
+    }
+
+    /// Detaches the program, reporting any error instead of deferring to `Drop`.
+    pub async fn detach(mut self) -> Result<()> {
+        self.detach_on_drop = false;
+        set_xdp_fd(
+            &self.handle,
+            &self.ifname,
+            self.index,
+            -1,
+            self.mode.flags(),
+        )
+        .await
+    }
+
+    /// Leaves the program attached when this value is dropped.
+    pub fn into_persistent(mut self) -> Self {
+        self.detach_on_drop = false;
+        self
+    }
+}
+
+impl Drop for XdpAttachment {
+    fn drop(&mut self) {
+        if !self.detach_on_drop {
+            return;
+        }
+        // As with `VethPair`, the connection task may be on the runtime this is dropped
+        // from, so detach over a fresh connection on a separate thread.
+        let netns = self.netns.clone();
+        let ifname = self.ifname.clone();
+        let (index, mode) = (self.index, self.mode);
+        let res = std::thread::spawn(move || -> Result<()> {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            rt.block_on(async {
+                let (handle, _) = new_connection_in(netns.as_ref())?;
+                set_xdp_fd(&handle, &ifname, index, -1, mode.flags()).await
+            })
+        })
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("detach thread panicked").into()));
+        match res {
+            Ok(()) | Err(VethError::LinkVanished(_)) => {}
+            Err(e) => eprintln!("failed to detach XDP program from {}: {}", self.ifname, e),
+        }
+    }
+}
+
+async fn set_xdp_fd(
+    handle: &Handle,
+    ifname: &str,
+    index: u32,
+    fd: RawFd,
+    flags: u32,
+) -> Result<()> {
+    let mut xdp = vec![LinkXdp::Fd(fd)];
+    if flags != 0 {
+        xdp.push(LinkXdp::Flags(flags));
+    }
+    let mut request = handle.link().set(index);
+    request
+        .message_mut()
+        .attributes
+        .push(LinkAttribute::Xdp(xdp));
+    request
+        .execute()
+        .await
+        .map_err(|e| match netlink_errno(&e) {
+            // A program is already attached in this or the other mode; the name is not taken.
+            Some(Errno::EBUSY) | Some(Errno::EEXIST) => e.into(),
+            _ => VethError::for_link(ifname, e),
+        })
+}
+
+/// The XDP program ids reported for a link, overall and per mode.
+#[derive(Debug, Default)]
+struct XdpProgIds {
+    any: Option<u32>,
+    native: Option<u32>,
+    generic: Option<u32>,
+}
+
+/// Opens the BPF object pinned at `path` on a BPF filesystem.
+fn bpf_obj_get(path: &Path) -> io::Result<OwnedFd> {
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))?;
+
+    // The `BPF_OBJ_GET` member of `union bpf_attr`.
+    #[repr(C)]
+    struct ObjGetAttr {
+        pathname: u64,
+        bpf_fd: u32,
+        file_flags: u32,
+    }
+    let attr = ObjGetAttr {
+        pathname: path.as_ptr() as u64,
+        bpf_fd: 0,
+        file_flags: 0,
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            BPF_OBJ_GET,
+            &attr as *const ObjGetAttr,
+            mem::size_of::<ObjGetAttr>(),
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+impl VethLink {
+    /// Attaches the XDP program referred to by `prog_fd` to this link.
+    ///
+    /// Fails with `EBUSY` if a program is already attached in the same mode, rather than
+    /// replacing it, so that dropping the older attachment cannot detach this program. The
+    /// kernel takes its own reference to the program, so `prog_fd` may be closed afterwards.
+    /// The program is detached when the returned attachment is dropped.
+    pub async fn attach_xdp(&self, prog_fd: RawFd, mode: XdpMode) -> Result<XdpAttachment> {
+        let flags = mode.flags() | XDP_FLAGS_UPDATE_IF_NOEXIST;
+        set_xdp_fd(&self.handle, &self.ifname, self.index, prog_fd, flags).await?;
+        let mut attachment = XdpAttachment {
+            ifname: self.ifname.clone(),
+            index: self.index,
+            netns: self.pinned_netns.clone(),
+            handle: self.handle.clone(),
+            mode,
+            prog_id: 0,
+            detach_on_drop: true,
+        };
+        let ids = self.xdp_prog_ids().await?;
+        let prog_id = match mode {
+            XdpMode::Auto => ids.any.or(ids.native).or(ids.generic),
+            XdpMode::Generic => ids.generic,
+            XdpMode::Native => ids.native,
+        };
+        attachment.prog_id = prog_id.ok_or_else(|| {
+            io::Error::other(format!(
+                "XDP program on {} missing after attach",
+                self.ifname
+            ))
+        })?;
+        Ok(attachment)
+    }
+
+    /// Attaches the XDP program pinned at `path` on a BPF filesystem, e.g. under
+    /// `/sys/fs/bpf`. See [`VethLink::attach_xdp`].
+    pub async fn attach_xdp_pinned(
+        &self,
+        path: impl AsRef<Path>,
+        mode: XdpMode,
+    ) -> Result<XdpAttachment> {
+        let prog = bpf_obj_get(path.as_ref()).map_err(|e| match e.raw_os_error() {
+            Some(libc::EPERM) | Some(libc::EACCES) => VethError::PermissionDenied,
+            _ => e.into(),
+        })?;
+        self.attach_xdp(prog.as_raw_fd(), mode).await
+    }
+
+    /// The id of the XDP program attached to this link, if any.
+    pub async fn xdp_prog_id(&self) -> Result<Option<u32>> {
+        let ids = self.xdp_prog_ids().await?;
+        // IFLA_XDP_PROG_ID is only reported when a single program is attached; with one per
+        // mode, report the native one, as `ip link` does.
+        Ok(ids.any.or(ids.native).or(ids.generic))
+    }
+
+    async fn xdp_prog_ids(&self) -> Result<XdpProgIds> {
+        let link = get_link(&self.handle, &self.ifname, self.index).await?;
+        let mut ids = XdpProgIds::default();
+        for attr in &link.attributes {
+            if let LinkAttribute::Xdp(xdp) = attr {
+                for nla in xdp {
+                    match nla {
+                        LinkXdp::ProgId(id) => ids.any = Some(*id),
+                        LinkXdp::DrvProgId(id) => ids.native = Some(*id),
+                        LinkXdp::SkbProgId(id) => ids.generic = Some(*id),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(ids)
+    }
+}